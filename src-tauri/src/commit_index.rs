@@ -0,0 +1,387 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use git2::{Oid, Repository};
+use serde::{Deserialize, Serialize};
+
+use crate::pr_document::{PullRequestThreadContext, ThreadProperties};
+
+/// The commit references of a full PR document, without the rest of the document
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrCommitRefs {
+    pub id: i32,
+    #[serde(default)]
+    pub last_merge_source_commit: Option<String>,
+    #[serde(default)]
+    pub last_merge_target_commit: Option<String>,
+    #[serde(default)]
+    pub last_merge_commit: Option<String>,
+    #[serde(default)]
+    pub threads: Vec<ThreadCommitRefs>,
+}
+
+/// The parts of a thread that reference iteration commits
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadCommitRefs {
+    #[serde(default)]
+    pub pull_request_thread_context: Option<PullRequestThreadContext>,
+    #[serde(default)]
+    pub properties: ThreadProperties,
+}
+
+impl PrCommitRefs {
+    // Source commits of the PR's iterations, as pushed (`RefUpdate` threads) or compared in
+    // file threads
+    fn iteration_commits(&self) -> impl Iterator<Item = &str> {
+        self.threads.iter().flat_map(|thread| {
+            let context = thread.pull_request_thread_context.as_ref();
+            [
+                thread.properties.ref_new_head_commit(),
+                context.and_then(|c| c.first_iteration_details.as_ref()?.source_commit.as_deref()),
+                context.and_then(|c| c.second_iteration_details.as_ref()?.source_commit.as_deref()),
+            ]
+            .into_iter()
+            .flatten()
+        })
+    }
+
+    /// Every commit the PR references: its merge, source and target commits, and the commits of
+    /// its iterations. May contain duplicates.
+    pub fn referenced_commits(&self) -> impl Iterator<Item = &str> {
+        let iteration_details = self.threads.iter()
+            .filter_map(|thread| thread.pull_request_thread_context.as_ref())
+            .flat_map(|context| [&context.first_iteration_details, &context.second_iteration_details])
+            .flatten()
+            .flat_map(|details| [&details.target_commit, &details.common_ref_commit])
+            .filter_map(|commit| commit.as_deref());
+
+        [
+            self.last_merge_commit.as_deref(),
+            self.last_merge_source_commit.as_deref(),
+            self.last_merge_target_commit.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.iteration_commits())
+        .chain(iteration_details)
+    }
+}
+
+/// Parse a commit id given as a full hash. `Oid::from_str` alone accepts prefixes, padding them
+/// with zeros, which would never match the commit they abbreviate.
+pub fn parse_commit_id(commit_id: &str) -> Option<Oid> {
+    if commit_id.len() != 40 {
+        return None;
+    }
+    Oid::from_str(commit_id).ok()
+}
+
+/// How a commit is referenced by the PR it was mapped to, from most to least specific
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommitRole {
+    Merge,
+    Source,
+    Iteration,
+}
+
+/// The PR a commit was mapped to
+#[derive(Debug, Clone, Serialize)]
+pub struct PrCommitMatch {
+    pub pr_number: i32,
+    pub role: CommitRole,
+    /// The archived commit that matched: the commit itself, or the mainline commit that
+    /// brought it in when found through the ancestry walk
+    pub matched_commit: String,
+    pub via_ancestry: bool,
+}
+
+/// Maps commits of the repository back to the PRs of the archive that introduced them
+pub struct CommitIndex {
+    // Commit id -> PR number and how the PR references it
+    commits: Mutex<Option<HashMap<Oid, (i32, CommitRole)>>>,
+    // First-parent history of HEAD (oldest first), cached for the HEAD it was walked from
+    mainline: Mutex<Option<(Oid, Vec<Oid>)>>,
+}
+
+impl CommitIndex {
+    pub fn new() -> Self {
+        CommitIndex {
+            commits: Mutex::new(None),
+            mainline: Mutex::new(None),
+        }
+    }
+
+    // Reset and rebuild the index from the commit references of every PR document
+    pub fn rebuild_index(&self, refs: Vec<PrCommitRefs>) {
+        let start = Instant::now();
+        let mut commits = HashMap::new();
+
+        for pr in &refs {
+            let references = [
+                (pr.last_merge_commit.as_deref(), CommitRole::Merge),
+                (pr.last_merge_source_commit.as_deref(), CommitRole::Source),
+            ]
+            .into_iter()
+            .filter_map(|(commit, role)| Some((commit?, role)))
+            .chain(pr.iteration_commits().map(|commit| (commit, CommitRole::Iteration)));
+
+            for (commit, role) in references {
+                let Some(oid) = parse_commit_id(commit) else {
+                    println!("Skipping invalid commit id '{}' of PR #{}", commit, pr.id);
+                    continue;
+                };
+
+                // A commit shared by several PRs (e.g. a reused branch) keeps its most specific
+                // role, and the first PR seen for that role
+                commits.entry(oid)
+                    .and_modify(|existing: &mut (i32, CommitRole)| {
+                        if role < existing.1 {
+                            *existing = (pr.id, role);
+                        }
+                    })
+                    .or_insert((pr.id, role));
+            }
+        }
+
+        println!("Performance: commit index built with {} commits from {} PRs in {:?}",
+                 commits.len(), refs.len(), start.elapsed());
+
+        *self.commits.lock().unwrap() = Some(commits);
+    }
+
+    // Look up the PR that references `commit_id` directly
+    pub fn lookup(&self, commit_id: Oid) -> Option<(i32, CommitRole)> {
+        self.commits.lock().unwrap()
+            .as_ref()?
+            .get(&commit_id)
+            .copied()
+    }
+
+    // Find the PR that introduced `commit_id`: either a PR references it directly, or it was
+    // merged through the first mainline commit of HEAD that contains it
+    pub fn find_pr_for_commit(
+        &self,
+        repo: &Repository,
+        commit_id: Oid,
+    ) -> Result<Option<PrCommitMatch>, git2::Error> {
+        if let Some((pr_number, role)) = self.lookup(commit_id) {
+            return Ok(Some(PrCommitMatch {
+                pr_number,
+                role,
+                matched_commit: commit_id.to_string(),
+                via_ancestry: false,
+            }));
+        }
+
+        let merged_by = match self.first_mainline_descendant(repo, commit_id)? {
+            Some(oid) if oid != commit_id => oid,
+            // On the mainline itself, so not brought in by a merge
+            _ => return Ok(None),
+        };
+
+        Ok(self.lookup(merged_by).map(|(pr_number, role)| PrCommitMatch {
+            pr_number,
+            role,
+            matched_commit: merged_by.to_string(),
+            via_ancestry: true,
+        }))
+    }
+
+    // The oldest commit of HEAD's first-parent history that is, or descends from, `commit_id`
+    fn first_mainline_descendant(&self, repo: &Repository, commit_id: Oid) -> Result<Option<Oid>, git2::Error> {
+        let head = repo.head()?.peel_to_commit()?.id();
+
+        let mut mainline_guard = self.mainline.lock().unwrap();
+        if mainline_guard.as_ref().is_none_or(|(cached_head, _)| *cached_head != head) {
+            let start = Instant::now();
+            let mut revwalk = repo.revwalk()?;
+            revwalk.push(head)?;
+            revwalk.simplify_first_parent()?;
+            let mut mainline = revwalk.collect::<Result<Vec<Oid>, _>>()?;
+            mainline.reverse();
+
+            println!("Performance: walked {} mainline commits from HEAD in {:?}",
+                     mainline.len(), start.elapsed());
+            *mainline_guard = Some((head, mainline));
+        }
+        let (_, mainline) = mainline_guard.as_ref().unwrap();
+
+        // Once a mainline commit contains the commit, every later one does too
+        let contains = |oid: Oid| -> Result<bool, git2::Error> {
+            Ok(oid == commit_id || repo.graph_descendant_of(oid, commit_id)?)
+        };
+        let (mut low, mut high) = (0, mainline.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if contains(mainline[mid])? {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        Ok(mainline.get(low).copied())
+    }
+
+    // Check if the index is initialized
+    pub fn is_initialized(&self) -> bool {
+        self.commits.lock().unwrap().is_some()
+    }
+
+    // Drop the built index so that it is rebuilt on next use
+    pub fn clear(&self) {
+        *self.commits.lock().unwrap() = None;
+        *self.mainline.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    // Commit an empty tree with the given parents, moving HEAD to it if `update_head` is set
+    fn commit(repo: &Repository, message: &str, update_head: bool, parents: &[Oid]) -> Oid {
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let parents: Vec<_> = parents.iter().map(|id| repo.find_commit(*id).unwrap()).collect();
+        let parents: Vec<_> = parents.iter().collect();
+
+        repo.commit(update_head.then_some("HEAD"), &signature, &signature, message, &tree, &parents).unwrap()
+    }
+
+    // A mainline of `root`, `merge` and `squash`, where `merge` merges a side branch of `side`
+    // and `source`. `dangling` forks from `root` but is never merged.
+    struct History {
+        _dir: TempDir,
+        repo: Repository,
+        root: Oid,
+        side: Oid,
+        source: Oid,
+        merge: Oid,
+        squash: Oid,
+        dangling: Oid,
+    }
+
+    fn history() -> History {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let root = commit(&repo, "Root", true, &[]);
+        let side = commit(&repo, "Side", false, &[root]);
+        let source = commit(&repo, "Source", false, &[side]);
+        let merge = commit(&repo, "Merge", true, &[root, source]);
+        let squash = commit(&repo, "Squash", true, &[merge]);
+        let dangling = commit(&repo, "Dangling", false, &[root]);
+
+        History { _dir: dir, repo, root, side, source, merge, squash, dangling }
+    }
+
+    fn refs(id: i32, merge: Oid, source: Option<Oid>) -> PrCommitRefs {
+        pr(id, Some(&merge.to_string()), source.map(|source| source.to_string()).as_deref())
+    }
+
+    fn find(index: &CommitIndex, history: &History, commit_id: Oid) -> Option<(i32, CommitRole, Oid, bool)> {
+        index.find_pr_for_commit(&history.repo, commit_id)
+            .unwrap()
+            .map(|found| (found.pr_number, found.role, Oid::from_str(&found.matched_commit).unwrap(), found.via_ancestry))
+    }
+
+    fn pr(id: i32, merge: Option<&str>, source: Option<&str>) -> PrCommitRefs {
+        PrCommitRefs {
+            id,
+            last_merge_source_commit: source.map(str::to_string),
+            last_merge_target_commit: None,
+            last_merge_commit: merge.map(str::to_string),
+            threads: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_only_full_commit_ids() {
+        let full = "0123456789abcdef0123456789abcdef01234567";
+
+        assert_eq!(parse_commit_id(full), Some(Oid::from_str(full).unwrap()));
+        assert_eq!(parse_commit_id(&full[..7]), None);
+        assert_eq!(parse_commit_id(&format!("{}0", full)), None);
+        assert_eq!(parse_commit_id("not-a-commit-not-a-commit-not-a-commit-!"), None);
+    }
+
+    #[test]
+    fn indexes_full_commit_ids_only() {
+        let full = "0123456789abcdef0123456789abcdef01234567";
+        let index = CommitIndex::new();
+        index.rebuild_index(vec![
+            pr(1, Some(full), Some("not-a-commit")),
+            pr(2, None, Some("abcdef1")),
+        ]);
+
+        assert_eq!(index.lookup(Oid::from_str(full).unwrap()), Some((1, CommitRole::Merge)));
+        // The zero-padded oid `Oid::from_str` makes of the short id
+        assert_eq!(index.lookup(Oid::from_str("abcdef1").unwrap()), None);
+    }
+
+    #[test]
+    fn finds_the_first_mainline_commit_containing_a_commit() {
+        let history = history();
+        let index = CommitIndex::new();
+        let descendant = |commit_id| index.first_mainline_descendant(&history.repo, commit_id).unwrap();
+
+        assert_eq!(descendant(history.root), Some(history.root));
+        assert_eq!(descendant(history.side), Some(history.merge));
+        assert_eq!(descendant(history.source), Some(history.merge));
+        assert_eq!(descendant(history.merge), Some(history.merge));
+        assert_eq!(descendant(history.squash), Some(history.squash));
+        assert_eq!(descendant(history.dangling), None);
+    }
+
+    #[test]
+    fn maps_commits_to_the_prs_that_introduced_them() {
+        let history = history();
+        let index = CommitIndex::new();
+        index.rebuild_index(vec![
+            refs(1, history.merge, Some(history.source)),
+            refs(2, history.squash, None),
+        ]);
+
+        // Referenced directly, on the mainline or not
+        assert_eq!(find(&index, &history, history.squash), Some((2, CommitRole::Merge, history.squash, false)));
+        assert_eq!(find(&index, &history, history.source), Some((1, CommitRole::Source, history.source, false)));
+        // Merged through the side branch
+        assert_eq!(find(&index, &history, history.side), Some((1, CommitRole::Merge, history.merge, true)));
+        // On the mainline, but before any PR
+        assert_eq!(find(&index, &history, history.root), None);
+        // Not reachable from HEAD
+        assert_eq!(find(&index, &history, history.dangling), None);
+    }
+
+    #[test]
+    fn keeps_the_most_specific_role_of_shared_commits() {
+        let history = history();
+        let index = CommitIndex::new();
+        index.rebuild_index(vec![
+            refs(3, history.squash, Some(history.merge)),
+            refs(1, history.merge, None),
+            refs(4, history.root, Some(history.merge)),
+        ]);
+
+        assert_eq!(index.lookup(history.merge), Some((1, CommitRole::Merge)));
+    }
+
+    #[test]
+    fn walks_the_mainline_again_when_head_moves() {
+        let history = history();
+        let index = CommitIndex::new();
+        index.rebuild_index(Vec::new());
+        assert_eq!(find(&index, &history, history.dangling), None);
+
+        let merge = commit(&history.repo, "Merge dangling", true, &[history.squash, history.dangling]);
+        index.rebuild_index(vec![refs(5, merge, None)]);
+
+        assert_eq!(find(&index, &history, history.dangling), Some((5, CommitRole::Merge, merge, true)));
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, FixedOffset};
+use git2::{BlameOptions, ObjectType, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::Path;
+
+use crate::commit_index::CommitIndex;
+use crate::git_commit::convert_git_time;
+
+/// Struct to hold the blame information of a single line
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlameLine {
+    /// Line number in the file at the blamed revision (1-based)
+    pub line_number: usize,
+    /// The content of the line
+    pub content: String,
+    /// The commit that last changed the line
+    pub commit_id: String,
+    /// The author of that commit
+    pub author_name: String,
+    pub author_email: String,
+    /// When the commit was authored, in the author's time zone
+    pub author_time: DateTime<FixedOffset>,
+    /// Line number in the commit that last changed the line
+    pub original_line_number: usize,
+    /// Path of the file in the commit that last changed the line, if it was renamed since
+    pub original_path: Option<String>,
+    /// The PR that introduced the commit, if it could be mapped to one of the archive
+    pub pr_number: Option<i32>,
+}
+
+/// Error type for blame operations in a git repository
+#[derive(Debug)]
+pub enum GitBlameError<'a> {
+    Git(git2::Error),
+    InvalidRange,
+    RevisionNotFound(&'a str),
+    TimeConversion,
+}
+
+impl From<git2::Error> for GitBlameError<'_> {
+    fn from(err: git2::Error) -> Self {
+        GitBlameError::Git(err)
+    }
+}
+
+impl std::fmt::Display for GitBlameError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitBlameError::Git(err) => write!(f, "Git error: {}", err),
+            GitBlameError::InvalidRange => write!(f, "Invalid line range"),
+            GitBlameError::RevisionNotFound(revision) => write!(f, "Revision not found: {}", revision),
+            GitBlameError::TimeConversion => write!(f, "Failed to convert git time"),
+        }
+    }
+}
+
+impl std::error::Error for GitBlameError<'_> {}
+
+/// Blames a range of lines of a file as of a specific Git revision.
+///
+/// # Arguments
+///
+/// * `repo` - The Git repository
+/// * `file_path` - Path to the file within the repository
+/// * `revision` - Git revision (commit hash, branch name, tag, etc.) to blame from
+/// * `line_range` - Range of lines to blame (1-based, inclusive start, inclusive end)
+/// * `commit_index` - Used to look up the PR that introduced each line, when available
+///
+/// # Returns
+///
+/// * `Result<Vec<BlameLine>, GitBlameError>` - One entry per line in the range, or an error
+///
+pub fn blame_range<'a>(
+    repo: &Repository,
+    file_path: &str,
+    revision: &'a str,
+    line_range: Range<usize>,
+    commit_index: Option<&CommitIndex>,
+) -> Result<Vec<BlameLine>, GitBlameError<'a>> {
+    // Validate the range
+    if line_range.start < 1 || line_range.start > line_range.end {
+        return Err(GitBlameError::InvalidRange);
+    }
+
+    // Resolve the revision to a commit
+    let commit = repo.revparse_single(revision)
+        .and_then(|obj| obj.peel(ObjectType::Commit))
+        .map_err(|_| GitBlameError::RevisionNotFound(revision))?
+        .into_commit()
+        .map_err(|_| GitBlameError::RevisionNotFound(revision))?;
+
+    // Read the file content at the revision, to return alongside the blame
+    let entry = commit.tree()?.get_path(Path::new(file_path))?;
+    let blob = repo.find_blob(entry.id())?;
+    let content = String::from_utf8_lossy(blob.content());
+    let lines: Vec<&str> = content.lines().collect();
+
+    if line_range.start > lines.len() {
+        return Err(GitBlameError::InvalidRange);
+    }
+    let end_line = line_range.end.min(lines.len());
+
+    // Only blame the requested lines, starting from the requested revision
+    let mut blame_opts = BlameOptions::new();
+    blame_opts
+        .newest_commit(commit.id())
+        .min_line(line_range.start)
+        .max_line(end_line);
+
+    let blame = repo.blame_file(Path::new(file_path), Some(&mut blame_opts))?;
+
+    // Hunks of the same commit share their PR lookup
+    let mut pr_numbers: HashMap<git2::Oid, Option<i32>> = HashMap::new();
+
+    let mut blamed_lines = Vec::with_capacity(end_line + 1 - line_range.start);
+    for line_number in line_range.start..=end_line {
+        let hunk = match blame.get_line(line_number) {
+            Some(hunk) => hunk,
+            None => continue,
+        };
+
+        let signature = hunk.final_signature();
+        let commit_id = hunk.final_commit_id();
+        let pr_number = *pr_numbers.entry(commit_id).or_insert_with(|| {
+            commit_index.and_then(|index| match index.find_pr_for_commit(repo, commit_id) {
+                Ok(pr_match) => pr_match.map(|pr_match| pr_match.pr_number),
+                Err(e) => {
+                    println!("Failed to look up the PR of commit {}: {}", commit_id, e);
+                    None
+                }
+            })
+        });
+        let offset = line_number - hunk.final_start_line();
+
+        blamed_lines.push(BlameLine {
+            line_number,
+            content: lines[line_number - 1].to_string(),
+            commit_id: commit_id.to_string(),
+            author_name: String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+            author_email: String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+            author_time: convert_git_time(signature.when()).map_err(|_| GitBlameError::TimeConversion)?,
+            original_line_number: hunk.orig_start_line() + offset,
+            original_path: hunk.path()
+                .and_then(|path| path.to_str())
+                .filter(|path| *path != file_path)
+                .map(str::to_string),
+            pr_number,
+        });
+    }
+
+    Ok(blamed_lines)
+}
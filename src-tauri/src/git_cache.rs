@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
+use std::sync::{Arc, Mutex};
+
+use git2::Oid;
+
+use crate::git_lines::DiffLineInfo;
+
+/// Memory budget of the cached file diffs
+pub const DIFF_CACHE_BYTES: usize = 64 * 1024 * 1024;
+/// Memory budget of the cached file lines
+pub const LINES_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Identifies the diff of a file between two of its versions, absent when the file doesn't exist
+/// on that side. Blob ids change with the content, so cached diffs never go stale, and the diff of
+/// a file unchanged between two iterations of a PR is only computed once.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiffKey {
+    pub file_path: String,
+    pub old_blob: Option<Oid>,
+    pub new_blob: Option<Oid>,
+}
+
+/// Results of git reads that are expensive to compute and requested over and over, such as the
+/// diff of a file commented on by several threads of a PR. Each kind of result has a memory
+/// budget, over which the least recently used results are dropped.
+pub struct GitCache {
+    diffs: SizedCache<DiffKey, Vec<DiffLineInfo>>,
+    lines: SizedCache<Oid, Vec<String>>,
+}
+
+impl GitCache {
+    pub fn new(diff_budget: usize, lines_budget: usize) -> Self {
+        GitCache {
+            diffs: SizedCache::new(diff_budget),
+            lines: SizedCache::new(lines_budget),
+        }
+    }
+
+    /// The lines of the diff of a file over the whole file, computed with `compute` if not cached
+    pub fn file_diff<E>(
+        &self,
+        key: DiffKey,
+        compute: impl FnOnce() -> Result<Vec<DiffLineInfo>, E>,
+    ) -> Result<Arc<Vec<DiffLineInfo>>, E> {
+        if let Some(lines) = self.diffs.get(&key) {
+            return Ok(lines);
+        }
+
+        let lines = compute()?;
+        let size = lines.iter()
+            .map(|line| size_of::<DiffLineInfo>() + line.content.len())
+            .sum();
+        Ok(self.diffs.insert(key, lines, size))
+    }
+
+    /// The lines of a blob, computed with `compute` if not cached
+    pub fn blob_lines<E>(
+        &self,
+        blob: Oid,
+        compute: impl FnOnce() -> Result<Vec<String>, E>,
+    ) -> Result<Arc<Vec<String>>, E> {
+        if let Some(lines) = self.lines.get(&blob) {
+            return Ok(lines);
+        }
+
+        let lines = compute()?;
+        let size = lines.iter()
+            .map(|line| size_of::<String>() + line.len())
+            .sum();
+        Ok(self.lines.insert(blob, lines, size))
+    }
+}
+
+impl Default for GitCache {
+    fn default() -> Self {
+        GitCache::new(DIFF_CACHE_BYTES, LINES_CACHE_BYTES)
+    }
+}
+
+// A map of values with their estimated size in memory, dropping the least recently used values
+// when over budget. Values are shared, so that a hit doesn't copy them.
+struct SizedCache<K, V> {
+    budget: usize,
+    state: Mutex<SizedCacheState<K, V>>,
+}
+
+struct SizedCacheState<K, V> {
+    entries: HashMap<K, CacheEntry<V>>,
+    size: usize,
+    // Incremented at each access, to order the entries by last use
+    clock: u64,
+}
+
+struct CacheEntry<V> {
+    value: Arc<V>,
+    size: usize,
+    last_used: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> SizedCache<K, V> {
+    fn new(budget: usize) -> Self {
+        SizedCache {
+            budget,
+            state: Mutex::new(SizedCacheState {
+                entries: HashMap::new(),
+                size: 0,
+                clock: 0,
+            }),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let clock = state.clock;
+
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = clock;
+        Some(Arc::clone(&entry.value))
+    }
+
+    // Values larger than the whole budget are returned without being cached
+    fn insert(&self, key: K, value: V, size: usize) -> Arc<V> {
+        let value = Arc::new(value);
+        if size > self.budget {
+            return value;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.clock += 1;
+        let entry = CacheEntry { value: Arc::clone(&value), size, last_used: state.clock };
+        if let Some(previous) = state.entries.insert(key, entry) {
+            state.size -= previous.size;
+        }
+        state.size += size;
+
+        // Evicting scans all the entries, which is cheap next to computing any of them
+        while state.size > self.budget {
+            let Some(oldest) = state.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(evicted) = state.entries.remove(&oldest) {
+                state.size -= evicted.size;
+            }
+        }
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_least_recently_used_values_over_budget() {
+        let cache: SizedCache<&str, u32> = SizedCache::new(10);
+        cache.insert("a", 1, 4);
+        cache.insert("b", 2, 4);
+
+        // Using "a" makes "b" the least recently used
+        assert_eq!(cache.get(&"a").as_deref(), Some(&1));
+        cache.insert("c", 3, 4);
+
+        assert_eq!(cache.get(&"a").as_deref(), Some(&1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c").as_deref(), Some(&3));
+    }
+
+    #[test]
+    fn returns_values_over_budget_without_caching_them() {
+        let cache: SizedCache<&str, u32> = SizedCache::new(10);
+        cache.insert("a", 1, 4);
+
+        assert_eq!(*cache.insert("big", 2, 11), 2);
+        assert_eq!(cache.get(&"big"), None);
+        assert_eq!(cache.get(&"a").as_deref(), Some(&1));
+    }
+
+    #[test]
+    fn computes_each_diff_once() {
+        let cache = GitCache::default();
+        let key = DiffKey { file_path: "file.txt".to_string(), old_blob: None, new_blob: Some(Oid::zero()) };
+        let mut computed = 0;
+
+        for _ in 0..3 {
+            let lines = cache.file_diff(key.clone(), || {
+                computed += 1;
+                Ok::<_, ()>(Vec::new())
+            });
+            assert!(lines.unwrap().is_empty());
+        }
+
+        assert_eq!(computed, 1);
+    }
+}
@@ -1,6 +1,7 @@
-use git2::{Repository, Time};
+use git2::{Commit, Repository, Sort, Time};
+use std::borrow::Cow;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset};
 
 /// Struct to hold commit metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,14 +16,45 @@ pub struct CommitMetadata {
     pub author_name: String,
     /// The author's email
     pub author_email: String,
-    /// The author time (when the commit was originally created)
-    pub author_time: DateTime<Local>,
+    /// The author time (when the commit was originally created), in the author's time zone
+    pub author_time: DateTime<FixedOffset>,
     /// The committer's name
     pub committer_name: String,
     /// The committer's email
     pub committer_email: String,
-    /// The commit time (when the commit was added to the repository)
-    pub commit_time: DateTime<Local>,
+    /// The commit time (when the commit was added to the repository), in the committer's time zone
+    pub commit_time: DateTime<FixedOffset>,
+    /// The full hashes of the parent commits, the first parent first
+    pub parent_ids: Vec<String>,
+    /// Changes against the first parent (or the empty tree for a root commit)
+    pub files_changed: usize,
+    /// Lines added and removed, when counted, as counting them reads every changed file
+    pub insertions: Option<usize>,
+    pub deletions: Option<usize>,
+    /// The kind of signature the commit carries, if it is signed. The signature isn't verified.
+    pub signature: Option<SignatureKind>,
+    /// Trailers at the end of the message (`Co-authored-by`, `Related work items`, etc.), in order
+    pub trailers: Vec<CommitTrailer>,
+    /// Whether the names, emails or message were not valid UTF-8, and had their invalid bytes
+    /// replaced
+    pub lossy_utf8: bool,
+}
+
+/// The format of a commit signature, from its armor header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureKind {
+    Gpg,
+    Ssh,
+    X509,
+    Unknown,
+}
+
+/// A `Key: value` trailer of a commit message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitTrailer {
+    pub key: String,
+    pub value: String,
 }
 
 /// Error type for commit metadata retrieval operations
@@ -30,7 +62,6 @@ pub struct CommitMetadata {
 pub enum CommitError {
     Git(git2::Error),
     RevisionNotFound,
-    InvalidUtf8,
     TimeConversion,
 }
 
@@ -45,7 +76,6 @@ impl std::fmt::Display for CommitError {
         match self {
             CommitError::Git(err) => write!(f, "Git error: {}", err),
             CommitError::RevisionNotFound => write!(f, "Revision not found"),
-            CommitError::InvalidUtf8 => write!(f, "Invalid UTF-8 in commit data"),
             CommitError::TimeConversion => write!(f, "Failed to convert git time"),
         }
     }
@@ -53,17 +83,22 @@ impl std::fmt::Display for CommitError {
 
 impl std::error::Error for CommitError {}
 
-/// Convert git2::Time to chrono::DateTime<Local>
-fn convert_git_time(git_time: Time) -> Result<DateTime<Local>, CommitError> {
+/// Convert git2::Time to chrono::DateTime<FixedOffset>, keeping the offset it was recorded with
+pub fn convert_git_time(git_time: Time) -> Result<DateTime<FixedOffset>, CommitError> {
     let seconds = git_time.seconds();
 
-    // Convert to local DateTime
-    let local_time = match DateTime::from_timestamp(seconds, 0) {
-        Some(time) => time.with_timezone(&Local),
+    let offset = match FixedOffset::east_opt(git_time.offset_minutes() * 60) {
+        Some(offset) => offset,
         None => return Err(CommitError::TimeConversion),
     };
 
-    Ok(local_time)
+    // Convert to a DateTime in the recorded time zone
+    let time = match DateTime::from_timestamp(seconds, 0) {
+        Some(time) => time.with_timezone(&offset),
+        None => return Err(CommitError::TimeConversion),
+    };
+
+    Ok(time)
 }
 
 /// Retrieves metadata for a specific commit
@@ -102,44 +137,62 @@ pub fn get_commit_metadata(
         }
     };
 
-    // Get commit ID
-    let commit_id = commit.id().to_string();
+    commit_metadata(repo, &commit, true)
+}
 
-    // Get commit message
-    let message = match commit.message() {
-        Some(msg) => msg.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
+/// Helper function to read the metadata of a commit, with its added and removed lines if
+/// `line_stats` is set
+fn commit_metadata(repo: &Repository, commit: &Commit, line_stats: bool) -> Result<CommitMetadata, CommitError> {
+    let mut lossy_utf8 = false;
+    let mut decode = |bytes: &[u8]| match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) => text.to_string(),
+        Cow::Owned(text) => {
+            lossy_utf8 = true;
+            text
+        }
     };
 
-    // Get commit summary (first line of message)
-    let summary = match commit.summary() {
-        Some(summary) => summary.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
-    };
+    // Get commit ID
+    let commit_id = commit.id().to_string();
+
+    // Get commit message, and its summary (first line)
+    let message = decode(commit.message_bytes());
+    let summary = commit.summary_bytes().map(&mut decode).unwrap_or_default();
+    let trailers = message_trailers(&message);
 
     // Get author information
     let author = commit.author();
-    let author_name = match author.name() {
-        Some(name) => name.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
-    };
-    let author_email = match author.email() {
-        Some(email) => email.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
-    };
+    let author_name = decode(author.name_bytes());
+    let author_email = decode(author.email_bytes());
     let author_time = convert_git_time(author.when())?;
 
     // Get committer information
     let committer = commit.committer();
-    let committer_name = match committer.name() {
-        Some(name) => name.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
+    let committer_name = decode(committer.name_bytes());
+    let committer_email = decode(committer.email_bytes());
+    let commit_time = convert_git_time(committer.when())?;
+
+    // Get the changes against the first parent
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
     };
-    let committer_email = match committer.email() {
-        Some(email) => email.to_string(),
-        None => return Err(CommitError::InvalidUtf8),
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+
+    // Changed files are known from the trees alone, while lines need the content of every file
+    let (insertions, deletions) = if line_stats {
+        let stats = diff.stats()?;
+        (Some(stats.insertions()), Some(stats.deletions()))
+    } else {
+        (None, None)
+    };
+
+    // A missing signature is reported as a not found error
+    let signature = match repo.extract_signature(&commit.id(), None) {
+        Ok((signature, _)) => Some(signature_kind(&signature)),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+        Err(err) => return Err(err.into()),
     };
-    let commit_time = convert_git_time(committer.when())?;
 
     Ok(CommitMetadata {
         commit_id,
@@ -151,5 +204,163 @@ pub fn get_commit_metadata(
         committer_name,
         committer_email,
         commit_time,
+        parent_ids: commit.parent_ids().map(|id| id.to_string()).collect(),
+        files_changed: diff.deltas().len(),
+        insertions,
+        deletions,
+        signature,
+        trailers,
+        lossy_utf8,
     })
-}
\ No newline at end of file
+}
+
+/// Helper function to tell the format of a signature from its armor header
+fn signature_kind(signature: &[u8]) -> SignatureKind {
+    let signature = String::from_utf8_lossy(signature);
+    let header = signature.trim_start().lines().next().unwrap_or("");
+
+    match header {
+        "-----BEGIN PGP SIGNATURE-----" => SignatureKind::Gpg,
+        "-----BEGIN SSH SIGNATURE-----" => SignatureKind::Ssh,
+        "-----BEGIN SIGNED MESSAGE-----" => SignatureKind::X509,
+        _ => SignatureKind::Unknown,
+    }
+}
+
+/// Helper function to parse the trailers of a commit message: its last paragraph, if every line
+/// of it is a `Key: value` trailer or the indented continuation of one. Unlike git, keys may
+/// contain spaces, as in the `Related work items` trailer added by Azure DevOps.
+fn message_trailers(message: &str) -> Vec<CommitTrailer> {
+    let message = message.trim_end();
+    let Some((_, last_paragraph)) = message.rsplit_once("\n\n") else {
+        // A message of a single paragraph is only a subject and body
+        return Vec::new();
+    };
+
+    let mut trailers: Vec<CommitTrailer> = Vec::new();
+    for line in last_paragraph.lines() {
+        if line.starts_with([' ', '\t']) {
+            match trailers.last_mut() {
+                Some(trailer) => {
+                    trailer.value.push(' ');
+                    trailer.value.push_str(line.trim());
+                    continue;
+                }
+                None => return Vec::new(),
+            }
+        }
+
+        let is_key = |key: &str| {
+            key.starts_with(|c: char| c.is_ascii_alphabetic())
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == ' ')
+        };
+        match line.split_once(':') {
+            Some((key, value)) if is_key(key) => trailers.push(CommitTrailer {
+                key: key.to_string(),
+                value: value.trim().to_string(),
+            }),
+            _ => return Vec::new(),
+        }
+    }
+
+    trailers
+}
+
+/// Lists the commits of a PR: those reachable from its source commit but not from its base
+///
+/// # Arguments
+///
+/// * `repo` - The Git repository
+/// * `source_revision` - The PR's source commit
+/// * `base_revision` - The merge base of the source commit with the target branch
+/// * `line_stats` - Whether to count the lines added and removed by each commit
+///
+/// # Returns
+///
+/// * `Result<Vec<CommitMetadata>, CommitError>` - The commits, newest first, or an error
+///
+pub fn get_pr_commit_series(
+    repo: &Repository,
+    source_revision: &str,
+    base_revision: &str,
+    line_stats: bool,
+) -> Result<Vec<CommitMetadata>, CommitError> {
+    let resolve = |revision: &str| {
+        repo.revparse_single(revision)
+            .and_then(|obj| obj.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|_| CommitError::RevisionNotFound)
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(resolve(source_revision)?)?;
+    revwalk.hide(resolve(base_revision)?)?;
+
+    revwalk
+        .map(|oid| commit_metadata(repo, &repo.find_commit(oid?)?, line_stats))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trailers(message: &str) -> Vec<(String, String)> {
+        message_trailers(message).into_iter()
+            .map(|trailer| (trailer.key, trailer.value))
+            .collect()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn parses_the_trailers_of_the_last_paragraph() {
+        let message = "Fix the parser\n\nSome details.\n\nCo-authored-by: Ana <ana@example.com>\nSigned-off-by: Bo <bo@example.com>\n";
+        assert_eq!(
+            trailers(message),
+            vec![pair("Co-authored-by", "Ana <ana@example.com>"), pair("Signed-off-by", "Bo <bo@example.com>")]
+        );
+    }
+
+    #[test]
+    fn accepts_keys_with_spaces() {
+        let message = "Merged PR 42: Fix the parser\n\nRelated work items: #123, #456";
+        assert_eq!(trailers(message), vec![pair("Related work items", "#123, #456")]);
+    }
+
+    #[test]
+    fn joins_continuation_lines_to_their_trailer() {
+        let message = "Fix the parser\n\nNote: the first line\n  and the second\n\tand the third\nAcked-by: Bo";
+        assert_eq!(
+            trailers(message),
+            vec![pair("Note", "the first line and the second and the third"), pair("Acked-by", "Bo")]
+        );
+    }
+
+    #[test]
+    fn ignores_a_last_paragraph_that_is_not_all_trailers() {
+        assert!(trailers("Fix the parser\n\nSee: the docs\nfor the details").is_empty());
+        assert!(trailers("Fix the parser\n\nEmpty input (e.g. \"\"): no longer crashes").is_empty());
+        assert!(trailers("Fix the parser\n\n  indented: before any trailer").is_empty());
+    }
+
+    #[test]
+    fn ignores_messages_of_a_single_paragraph() {
+        assert!(trailers("Signed-off-by: Bo <bo@example.com>\n").is_empty());
+    }
+
+    #[test]
+    fn tells_signature_kinds_from_their_armor() {
+        let signature = |header: &str| format!("{}\n\nabcdef\n-----END-----\n", header).into_bytes();
+
+        assert_eq!(signature_kind(&signature("-----BEGIN PGP SIGNATURE-----")), SignatureKind::Gpg);
+        assert_eq!(signature_kind(&signature("-----BEGIN SSH SIGNATURE-----")), SignatureKind::Ssh);
+        assert_eq!(signature_kind(&signature("-----BEGIN SIGNED MESSAGE-----")), SignatureKind::X509);
+        assert_eq!(signature_kind(&signature("\n  -----BEGIN PGP SIGNATURE-----")), SignatureKind::Gpg);
+        assert_eq!(signature_kind(b"not a signature"), SignatureKind::Unknown);
+        assert_eq!(signature_kind(b""), SignatureKind::Unknown);
+    }
+}
@@ -1,8 +1,11 @@
-use git2::{Diff, DiffOptions, Error, Object, ObjectType, Repository};
-use std::collections::HashMap;
+use git2::{Diff, DiffDelta, DiffFindOptions, DiffOptions, Error, Object, ObjectType, Patch, Repository};
+use std::cell::RefCell;
 use std::str;
 use serde::{Deserialize, Serialize};
 
+use crate::highlight::{highlight_lines, HighlightSpan};
+use crate::word_diff::{annotate_word_changes, ChangedRange};
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DiffEntry {
     pub file_path: String,
@@ -16,8 +19,20 @@ pub struct FileDiff {
     pub old_file: String,
     pub new_file: String,
     pub status: char,   // 'A' for added, 'M' for modified, 'D' for deleted
-    pub lines: Vec<LineDiff>,
+    pub hunks: Vec<HunkDiff>,
     pub binary: bool,
+    pub similarity: Option<u16>,    // Percentage, for renamed ('R') and copied ('C') files
+}
+
+/// A contiguous block of changes within a file, with its surrounding context lines
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HunkDiff {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub header: String, // e.g. "@@ -12,7 +12,8 @@ fn main() {"
+    pub lines: Vec<LineDiff>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,6 +41,13 @@ pub struct LineDiff {
     pub new_lineno: Option<u32>,
     pub content: String,
     pub origin: char,   // '+' for addition, '-' for deletion, ' ' for context
+    /// Changed ranges within the line, when word diff is enabled and the line was paired with
+    /// the line it replaced or was replaced by
+    #[serde(default)]
+    pub changes: Vec<ChangedRange>,
+    /// Syntax highlighting of the line, when enabled and the language of the file is known
+    #[serde(default)]
+    pub highlights: Option<Vec<HighlightSpan>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -33,6 +55,92 @@ pub struct TreeDiff {
     pub files: Vec<FileDiff>,
 }
 
+/// A changed file without its lines, to list large diffs before loading them file by file
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileDiffSummary {
+    pub old_file: String,
+    pub new_file: String,
+    pub status: char,
+    pub binary: bool,
+    pub similarity: Option<u16>,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TreeDiffSummary {
+    pub files: Vec<FileDiffSummary>,
+}
+
+/// Settings applied to every tree diff
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiffSettings {
+    /// Pair deleted and added files with similar content as renames
+    pub detect_renames: bool,
+    /// Pair added files with similar modified files as copies
+    pub detect_copies: bool,
+    /// Minimum similarity (percentage) for a rename or copy
+    pub similarity_threshold: u16,
+    /// Unchanged lines shown around each change
+    pub context_lines: u32,
+    /// Ignore all whitespace when comparing lines
+    pub ignore_whitespace: bool,
+    /// Ignore changes in the amount of whitespace
+    pub ignore_whitespace_change: bool,
+    /// Ignore whitespace at the end of lines
+    pub ignore_whitespace_eol: bool,
+    /// Annotate changed lines with the ranges that changed within them
+    pub word_diff: bool,
+    /// Annotate lines with their syntax highlighting, computed over the whole file. Off by
+    /// default, as it parses both sides of every file; the per-file commands opt in.
+    pub syntax_highlight: bool,
+}
+
+impl Default for DiffSettings {
+    fn default() -> Self {
+        DiffSettings {
+            detect_renames: true,
+            detect_copies: false,
+            // Same as git's default
+            similarity_threshold: 50,
+            context_lines: 3,
+            ignore_whitespace: false,
+            ignore_whitespace_change: false,
+            ignore_whitespace_eol: false,
+            word_diff: false,
+            syntax_highlight: false,
+        }
+    }
+}
+
+/// The line ranges of a hunk, as in its header
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct HunkRange {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+}
+
+impl HunkRange {
+    // The first line of each side covered by the hunk. A side without lines starts after the
+    // line it was removed from or added at, like in unified diffs
+    fn first_lines(&self) -> (u32, u32) {
+        let old_first = if self.old_lines == 0 { self.old_start.saturating_add(1) } else { self.old_start };
+        let new_first = if self.new_lines == 0 { self.new_start.saturating_add(1) } else { self.new_start };
+        (old_first, new_first)
+    }
+}
+
+/// Which side of a hunk to expand the context of
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextDirection {
+    Above,
+    Below,
+}
+
 /// Error type for diff operations in a git repository
 #[derive(Debug)]
 pub enum GitDiffError<'a> {
@@ -69,12 +177,42 @@ fn tree_to_treeish<'a>(
     Ok(tree)
 }
 
-/// Get a diff of the whole tree between two revisions
-pub fn get_tree_diff_between_revisions<'a>(
+/// Find the best common ancestor of two revisions, where a branch last forked from or synced
+/// with the other
+pub fn get_merge_base<'a>(
+    repo: &Repository,
+    revision: &'a str,
+    other_revision: &'a str,
+) -> Result<String, GitDiffError<'a>> {
+    let commit = repo.revparse_single(revision)
+        .and_then(|obj| obj.peel_to_commit())
+        .map_err(|_| GitDiffError::RevisionNotFound(revision))?;
+    let other_commit = repo.revparse_single(other_revision)
+        .and_then(|obj| obj.peel_to_commit())
+        .map_err(|_| GitDiffError::RevisionNotFound(other_revision))?;
+
+    let merge_base = repo.merge_base(commit.id(), other_commit.id())?;
+    Ok(merge_base.to_string())
+}
+
+/// Which files of the trees a diff covers
+#[derive(Clone, Copy)]
+enum DiffPaths<'p> {
+    All,
+    /// Files matching a pathspec, e.g. `src/*.rs`
+    Pattern(&'p str),
+    /// A single file, by its exact path
+    File(&'p str),
+}
+
+/// Generate the diff between the trees of two revisions, limited to some of their files
+fn diff_revisions<'a>(
     repo: &'a Repository,
     from_revision: &'a str,
     to_revision: &'a str,
-) -> Result<TreeDiff, GitDiffError<'a>> {
+    paths: DiffPaths,
+    settings: &DiffSettings,
+) -> Result<Diff<'a>, GitDiffError<'a>> {
     // Get the tree objects for both revisions
     let from_tree = tree_to_treeish(repo, from_revision)?;
     let to_tree = tree_to_treeish(repo, to_revision)?;
@@ -88,142 +226,675 @@ pub fn get_tree_diff_between_revisions<'a>(
 
     // Set up diff options
     let mut diff_opts = DiffOptions::new();
-    diff_opts.context_lines(3); // Default context lines
+    diff_opts
+        .context_lines(settings.context_lines)
+        .ignore_whitespace(settings.ignore_whitespace)
+        .ignore_whitespace_change(settings.ignore_whitespace_change)
+        .ignore_whitespace_eol(settings.ignore_whitespace_eol);
+    match paths {
+        DiffPaths::All => {},
+        DiffPaths::Pattern(pattern) => {
+            diff_opts.pathspec(pattern);
+        },
+        DiffPaths::File(path) => {
+            // The path is matched as is, even if it has wildcard characters
+            diff_opts.pathspec(path).disable_pathspec_match(true);
+        },
+    }
 
     // Generate the diff between the two trees
-    let diff = repo.diff_tree_to_tree(
+    let mut diff = repo.diff_tree_to_tree(
         Some(from_tree_ref),
         Some(to_tree_ref),
         Some(&mut diff_opts),
     )?;
+    find_similar(&mut diff, settings)?;
 
-    // Process the diff to collect file changes
-    process_tree_diff(diff)
-        .map_err(|err| GitDiffError::Git(err))
-}
-
-fn process_tree_diff(diff: Diff) -> Result<TreeDiff, Error> {
-    let mut files = Vec::new();
-    let mut file_line_map = HashMap::new();
-
-    // First pass: create file entries and initialize their line collections
-    for (file_index, delta) in diff.deltas().enumerate() {
-        let status = match delta.status() {
-            git2::Delta::Added => 'A',
-            git2::Delta::Deleted => 'D',
-            git2::Delta::Modified => 'M',
-            git2::Delta::Renamed => 'R',
-            git2::Delta::Copied => 'C',
-            git2::Delta::Ignored => 'I',
-            git2::Delta::Untracked => 'U',
-            git2::Delta::Typechange => 'T',
-            git2::Delta::Unreadable => 'X',
-            git2::Delta::Unmodified => ' ',
-            _ => '?',
-        };
+    Ok(diff)
+}
 
-        let binary = delta.old_file().is_binary() || delta.new_file().is_binary();
+/// Get a diff of the whole tree between two revisions
+pub fn get_tree_diff_between_revisions<'a>(
+    repo: &'a Repository,
+    from_revision: &'a str,
+    to_revision: &'a str,
+    settings: &DiffSettings,
+) -> Result<TreeDiff, GitDiffError<'a>> {
+    let diff = diff_revisions(repo, from_revision, to_revision, DiffPaths::All, settings)?;
 
-        // Create new file entry
-        files.push(FileDiff {
-            old_file: delta.old_file().path().and_then(|p| p.to_str()).unwrap_or("").to_string(),
-            new_file: delta.new_file().path().and_then(|p| p.to_str()).unwrap_or("").to_string(),
-            status,
-            lines: Vec::new(), // Start with empty lines vector
-            binary,
-        });
+    // Process the diff to collect file changes
+    process_tree_diff(repo, &diff, settings)
+        .map_err(GitDiffError::Git)
+}
 
-        // Only set up line tracking for non-binary files
-        if !binary {
-            file_line_map.insert(file_index, Vec::new());
-        }
-    }
+/// Get a diff of the whole tree between two revisions with filtering options
+pub fn get_filtered_tree_diff<'a>(
+    repo: &'a Repository,
+    from_revision: &'a str,
+    to_revision: &'a str,
+    file_pattern: &str,
+    settings: &DiffSettings,
+) -> Result<TreeDiff, GitDiffError<'a>> {
+    let diff = diff_revisions(repo, from_revision, to_revision, DiffPaths::Pattern(file_pattern), settings)?;
+
+    // Process the diff to collect file changes
+    process_tree_diff(repo, &diff, settings)
+        .map_err(GitDiffError::Git)
+}
 
-    // Second pass: populate line information
-    if !file_line_map.is_empty() {
-        // Use a file index to track which file we're currently processing
-        let mut current_file_index = 0;
-
-        diff.print(git2::DiffFormat::Patch, |print_delta, _hunk, line| {
-            // Delta header indicates we're moving to a new file
-            if print_delta.status() != git2::Delta::Unmodified {
-                // Find the corresponding file index for this delta
-                if let Some(index) = files.iter().position(|f| {
-                    let old_match = print_delta.old_file().path().and_then(|p| p.to_str())
-                        .map_or(false, |path| path == f.old_file);
-                    let new_match = print_delta.new_file().path().and_then(|p| p.to_str())
-                        .map_or(false, |path| path == f.new_file);
-                    old_match || new_match
-                }) {
-                    current_file_index = index;
+/// Get the changed files between two revisions with their line counts, without their content
+pub fn get_tree_diff_summary<'a>(
+    repo: &'a Repository,
+    from_revision: &'a str,
+    to_revision: &'a str,
+    file_pattern: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<TreeDiffSummary, GitDiffError<'a>> {
+    let paths = file_pattern.map_or(DiffPaths::All, DiffPaths::Pattern);
+    let diff = diff_revisions(repo, from_revision, to_revision, paths, settings)?;
+
+    // The added and deleted lines of each file, counted in a single pass over the diff instead of
+    // building a patch per file. Files come in the order of the deltas, the current one last.
+    // Loading their contents also flags the binary ones.
+    let line_counts = RefCell::new(Vec::with_capacity(diff.deltas().len()));
+    diff.foreach(
+        &mut |_, _| {
+            line_counts.borrow_mut().push((0, 0));
+            true
+        },
+        None,
+        None,
+        Some(&mut |_, _, line| {
+            if let Some((additions, deletions)) = line_counts.borrow_mut().last_mut() {
+                match line.origin() {
+                    '+' => *additions += 1,
+                    '-' => *deletions += 1,
+                    _ => {},
                 }
             }
+            true
+        }),
+    )?;
+
+    let line_counts = line_counts.into_inner();
+    let files = diff.deltas()
+        .zip(line_counts)
+        .map(|(delta, (additions, deletions))| summarize_file_diff(repo, &delta, additions, deletions))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(TreeDiffSummary { files })
+}
+
+/// Get the diff of a single file between two revisions. The file can be named by either its old
+/// or its new path, so that renamed files are found under both.
+pub fn get_file_diff<'a>(
+    repo: &'a Repository,
+    from_revision: &'a str,
+    to_revision: &'a str,
+    file_path: &str,
+    settings: &DiffSettings,
+) -> Result<FileDiff, GitDiffError<'a>> {
+    let mut diff = diff_revisions(repo, from_revision, to_revision, DiffPaths::File(file_path), settings)?;
+    let mut delta_index = find_file_delta(&diff, file_path);
+
+    // Renames and copies pair added and deleted files across the whole tree, so those are only
+    // known from a diff of all the files. A file changed in place is the same either way.
+    let changed_in_place = delta_index
+        .and_then(|index| diff.get_delta(index))
+        .is_some_and(|delta| matches!(delta.status(), git2::Delta::Modified | git2::Delta::Typechange));
+    if (settings.detect_renames || settings.detect_copies) && !changed_in_place {
+        diff = diff_revisions(repo, from_revision, to_revision, DiffPaths::All, settings)?;
+        delta_index = find_file_delta(&diff, file_path);
+    }
 
-            // Extract line information
-            let origin = line.origin();
-            let content = match str::from_utf8(line.content()) {
-                Ok(s) => s.trim_end().to_string(),
-                Err(_) => "[Binary content]".to_string(),
+    let delta_index = delta_index
+        .ok_or_else(|| GitDiffError::Generic(format!("File not changed between revisions: {}", file_path)))?;
+
+    process_file_diff(repo, &diff, delta_index, settings)
+        .map_err(GitDiffError::Git)
+}
+
+/// Find the change to a file in a diff by its path. The file at that path comes first, as a copy
+/// leaves its source in place and the path of a renamed file can be reused by a new one. The old
+/// path of a file only names it if the file is gone from there, deleted or renamed.
+fn find_file_delta(diff: &Diff, file_path: &str) -> Option<usize> {
+    let is_file = |file: git2::DiffFile| file.path().and_then(|p| p.to_str()) == Some(file_path);
+
+    diff.deltas()
+        .position(|delta| is_file(delta.new_file()))
+        .or_else(|| diff.deltas().position(|delta| {
+            matches!(delta.status(), git2::Delta::Deleted | git2::Delta::Renamed) && is_file(delta.old_file())
+        }))
+}
+
+/// Get up to `count` unchanged lines directly above or below a hunk of a file's diff, to expand
+/// its context. Lines are read from the file at `to_revision`, or at `from_revision` if it was
+/// deleted.
+#[allow(clippy::too_many_arguments)]
+pub fn get_context_lines<'a>(
+    repo: &'a Repository,
+    file_path: &str,
+    from_revision: &'a str,
+    to_revision: &'a str,
+    hunk: &HunkRange,
+    direction: ContextDirection,
+    count: u32,
+    settings: &DiffSettings,
+) -> Result<Vec<LineDiff>, GitDiffError<'a>> {
+    type FileLines = (Vec<String>, Option<Vec<Vec<HighlightSpan>>>);
+    let read_lines = |revision: &'a str| -> Result<Option<FileLines>, GitDiffError<'a>> {
+        let tree = tree_to_treeish(repo, revision)?;
+        let tree = tree.as_tree().ok_or_else(||
+            GitDiffError::Generic(format!("Failed to get tree for revision: {}", revision)))?;
+
+        let entry = match tree.get_path(std::path::Path::new(file_path)) {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+        let blob = repo.find_blob(entry.id())?;
+        let content = String::from_utf8_lossy(blob.content());
+        let highlights = if settings.syntax_highlight {
+            highlight_lines(file_path, &content)
+        } else {
+            None
+        };
+
+        Ok(Some((content.lines().map(|line| line.trim_end().to_string()).collect(), highlights)))
+    };
+
+    let (old_first, new_first) = hunk.first_lines();
+
+    // Unchanged lines are the same on both sides, so they are read from one and numbered for both
+    let ((lines, highlights), first, hunk_lines, other_first, other_hunk_lines, is_new_side) = match read_lines(to_revision)? {
+        Some(file) => (file, new_first, hunk.new_lines, old_first, hunk.old_lines, true),
+        None => match read_lines(from_revision)? {
+            Some(file) => (file, old_first, hunk.old_lines, new_first, hunk.new_lines, false),
+            None => return Err(GitDiffError::Generic(format!("File not found in either revision: {}", file_path))),
+        },
+    };
+
+    let (start, end, offset) = match direction {
+        ContextDirection::Above => (
+            first.saturating_sub(count).max(1),
+            first,
+            other_first as i64 - first as i64,
+        ),
+        ContextDirection::Below => {
+            // The hunk comes from the frontend, so its numbers may be anything
+            let after = first.saturating_add(hunk_lines);
+            (
+                after,
+                after.saturating_add(count).min(lines.len() as u32 + 1),
+                other_first as i64 + other_hunk_lines as i64 - after as i64,
+            )
+        },
+    };
+
+    let context = (start..end)
+        .filter_map(|lineno| {
+            let content = lines.get(lineno as usize - 1)?.clone();
+            let other_lineno = u32::try_from(lineno as i64 + offset).ok().filter(|&n| n > 0);
+            let (old_lineno, new_lineno) = if is_new_side {
+                (other_lineno, Some(lineno))
+            } else {
+                (Some(lineno), other_lineno)
             };
 
-            let old_lineno = line.old_lineno();
-            let new_lineno = line.new_lineno();
+            let highlights = highlights.as_ref()
+                .and_then(|highlights| highlights.get(lineno as usize - 1))
+                .cloned();
+
+            Some(LineDiff { old_lineno, new_lineno, content, origin: ' ', changes: Vec::new(), highlights })
+        })
+        .collect();
+
+    Ok(context)
+}
+
+/// Pair deleted and added files into renames and copies, as configured
+fn find_similar(diff: &mut Diff, settings: &DiffSettings) -> Result<(), Error> {
+    if !settings.detect_renames && !settings.detect_copies {
+        return Ok(());
+    }
+
+    let mut find_opts = DiffFindOptions::new();
+    find_opts
+        .renames(settings.detect_renames)
+        .copies(settings.detect_copies)
+        .rename_threshold(settings.similarity_threshold)
+        .copy_threshold(settings.similarity_threshold);
+
+    diff.find_similar(Some(&mut find_opts))
+}
+
+/// Estimate how similar the two sides of a renamed or copied file are, as the percentage of
+/// lines they have in common. libgit2 computes its own score while pairing files, but git2
+/// doesn't expose it. Binary files have no lines, so their similarity is only known when they
+/// are identical.
+fn estimate_similarity(repo: &Repository, delta: &DiffDelta, deletions: usize) -> Result<Option<u16>, Error> {
+    if delta.old_file().id() == delta.new_file().id() {
+        return Ok(Some(100));
+    }
+
+    let old_blob = repo.find_blob(delta.old_file().id())?;
+    let new_blob = repo.find_blob(delta.new_file().id())?;
+    if old_blob.is_binary() || new_blob.is_binary() {
+        return Ok(None);
+    }
+
+    let count_lines = |blob: &git2::Blob| blob.content().split_inclusive(|&b| b == b'\n').count();
+    let old_lines = count_lines(&old_blob);
+    let new_lines = count_lines(&new_blob);
+    if old_lines + new_lines == 0 {
+        return Ok(Some(100));
+    }
+
+    let common_lines = old_lines.saturating_sub(deletions);
+
+    Ok(Some((200 * common_lines / (old_lines + new_lines)) as u16))
+}
+
+fn delta_status(delta: &DiffDelta) -> char {
+    match delta.status() {
+        git2::Delta::Added => 'A',
+        git2::Delta::Deleted => 'D',
+        git2::Delta::Modified => 'M',
+        git2::Delta::Renamed => 'R',
+        git2::Delta::Copied => 'C',
+        git2::Delta::Ignored => 'I',
+        git2::Delta::Untracked => 'U',
+        git2::Delta::Typechange => 'T',
+        git2::Delta::Unreadable => 'X',
+        git2::Delta::Unmodified => ' ',
+        _ => '?',
+    }
+}
+
+fn delta_path(file: git2::DiffFile) -> String {
+    file.path().and_then(|p| p.to_str()).unwrap_or("").to_string()
+}
+
+/// Highlight the whole content of one side of a file's diff, if the file exists on that side.
+/// Submodules have a commit instead of content, and are left unhighlighted.
+fn highlight_diff_file(repo: &Repository, file: git2::DiffFile) -> Result<Option<Vec<Vec<HighlightSpan>>>, Error> {
+    if file.id().is_zero() || !matches!(file.mode(), git2::FileMode::Blob | git2::FileMode::BlobExecutable) {
+        return Ok(None);
+    }
+
+    let blob = repo.find_blob(file.id())?;
+    let content = String::from_utf8_lossy(blob.content());
+
+    Ok(highlight_lines(&delta_path(file), &content))
+}
+
+/// Summarize the changes to a single file of a diff, once its contents were loaded to know
+/// whether it is binary
+fn summarize_file_diff(
+    repo: &Repository,
+    delta: &DiffDelta,
+    additions: usize,
+    deletions: usize,
+) -> Result<FileDiffSummary, Error> {
+    let similarity = match delta.status() {
+        git2::Delta::Renamed | git2::Delta::Copied => estimate_similarity(repo, delta, deletions)?,
+        _ => None,
+    };
+
+    Ok(FileDiffSummary {
+        old_file: delta_path(delta.old_file()),
+        new_file: delta_path(delta.new_file()),
+        status: delta_status(delta),
+        binary: delta.old_file().is_binary() || delta.new_file().is_binary(),
+        similarity,
+        additions,
+        deletions,
+    })
+}
+
+/// Collect the changes to a single file of a diff, including its hunks
+fn process_file_diff(
+    repo: &Repository,
+    diff: &Diff,
+    delta_index: usize,
+    settings: &DiffSettings,
+) -> Result<FileDiff, Error> {
+    // The patch also loads the file contents, which is needed to know whether they're binary
+    let patch = Patch::from_diff(diff, delta_index)?;
+    let summary = match &patch {
+        Some(patch) => {
+            let (_, additions, deletions) = patch.line_stats()?;
+            summarize_file_diff(repo, &patch.delta(), additions, deletions)?
+        },
+        None => {
+            let delta = diff.get_delta(delta_index)
+                .ok_or_else(|| Error::from_str("Delta index out of range"))?;
+            summarize_file_diff(repo, &delta, 0, 0)?
+        },
+    };
+
+    let mut hunks = Vec::new();
+    // Binary files have no lines to show
+    if let Some(patch) = patch.filter(|_| !summary.binary) {
+        // Both sides are highlighted as a whole, and their lines picked by line number
+        let (old_highlights, new_highlights) = if settings.syntax_highlight {
+            let delta = patch.delta();
+            (highlight_diff_file(repo, delta.old_file())?, highlight_diff_file(repo, delta.new_file())?)
+        } else {
+            (None, None)
+        };
+
+        for hunk_index in 0..patch.num_hunks() {
+            let (hunk, line_count) = patch.hunk(hunk_index)?;
+
+            let mut lines = Vec::with_capacity(line_count);
+            for line_index in 0..line_count {
+                let line = patch.line_in_hunk(hunk_index, line_index)?;
+
+                // Extract line information
+                let content = match str::from_utf8(line.content()) {
+                    Ok(s) => s.trim_end().to_string(),
+                    Err(_) => "[Binary content]".to_string(),
+                };
+
+                let (side_highlights, lineno) = match line.origin() {
+                    '-' => (&old_highlights, line.old_lineno()),
+                    _ => (&new_highlights, line.new_lineno()),
+                };
+                let highlights = side_highlights.as_ref()
+                    .zip(lineno)
+                    .and_then(|(highlights, lineno)| highlights.get(lineno as usize - 1))
+                    .cloned();
 
-            // Add line info to the current file's lines collection
-            if let Some(lines) = file_line_map.get_mut(&current_file_index) {
                 lines.push(LineDiff {
-                    old_lineno,
-                    new_lineno,
+                    old_lineno: line.old_lineno(),
+                    new_lineno: line.new_lineno(),
                     content,
-                    origin,
+                    origin: line.origin(),
+                    changes: Vec::new(),
+                    highlights,
                 });
             }
 
-            true
-        })?;
-    }
+            if settings.word_diff {
+                annotate_word_changes(&mut lines);
+            }
 
-    // Final pass: update each file with its line information
-    for (index, lines) in file_line_map {
-        if let Some(file) = files.get_mut(index) {
-            file.lines = lines;
+            hunks.push(HunkDiff {
+                old_start: hunk.old_start(),
+                old_lines: hunk.old_lines(),
+                new_start: hunk.new_start(),
+                new_lines: hunk.new_lines(),
+                header: String::from_utf8_lossy(hunk.header()).trim_end().to_string(),
+                lines,
+            });
         }
     }
 
+    Ok(FileDiff {
+        old_file: summary.old_file,
+        new_file: summary.new_file,
+        status: summary.status,
+        hunks,
+        binary: summary.binary,
+        similarity: summary.similarity,
+    })
+}
+
+fn process_tree_diff(repo: &Repository, diff: &Diff, settings: &DiffSettings) -> Result<TreeDiff, Error> {
+    let files = (0..diff.deltas().len())
+        .map(|delta_index| process_file_diff(repo, diff, delta_index, settings))
+        .collect::<Result<Vec<_>, _>>()?;
+
     Ok(TreeDiff { files })
 }
 
-/// Get a diff of the whole tree between two revisions with filtering options
-pub fn get_filtered_tree_diff<'a>(
-    repo: &'a Repository,
-    from_revision: &'a str,
-    to_revision: &'a str,
-    file_pattern: &str,
-) -> Result<TreeDiff, GitDiffError<'a>> {
-    // Get the tree objects for both revisions
-    let from_tree = tree_to_treeish(repo, from_revision)?;
-    let to_tree = tree_to_treeish(repo, to_revision)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Oid, Signature};
+    use tempfile::TempDir;
+
+    // Create a repository with one commit per list of files, each commit holding only its files
+    fn repo_with_commits(commits: &[&[(&str, &str)]]) -> (TempDir, Repository, Vec<String>) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+
+        let mut ids: Vec<Oid> = Vec::new();
+        for (index, files) in commits.iter().enumerate() {
+            let mut builder = repo.treebuilder(None).unwrap();
+            for (path, content) in files.iter() {
+                let blob = repo.blob(content.as_bytes()).unwrap();
+                builder.insert(path, blob, 0o100644).unwrap();
+            }
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
 
-    // Get the tree references
-    let from_tree_ref = from_tree.as_tree().ok_or_else(||
-        GitDiffError::Generic(format!("Failed to get tree for revision: {}", from_revision)))?;
+            let parents = ids.last().map(|id| repo.find_commit(*id).unwrap());
+            let parents: Vec<_> = parents.iter().collect();
+            let message = format!("Commit {}", index);
+            ids.push(repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &parents).unwrap());
+        }
 
-    let to_tree_ref = to_tree.as_tree().ok_or_else(||
-        GitDiffError::Generic(format!("Failed to get tree for revision: {}", to_revision)))?;
+        let ids = ids.iter().map(Oid::to_string).collect();
+        (dir, repo, ids)
+    }
 
-    // Set up diff options
-    let mut diff_opts = DiffOptions::new();
-    diff_opts.context_lines(3); // Default context lines
-    diff_opts.pathspec(file_pattern);
+    // Lines "line 1" to "line `count`", with `edit` applied to each (line number, line)
+    fn numbered_lines(count: usize, edit: impl Fn(usize, String) -> Option<String>) -> String {
+        (1..=count)
+            .filter_map(|n| edit(n, format!("line {}", n)))
+            .map(|line| line + "\n")
+            .collect()
+    }
 
-    // Generate the diff between the two trees
-    let diff = repo.diff_tree_to_tree(
-        Some(from_tree_ref),
-        Some(to_tree_ref),
-        Some(&mut diff_opts),
-    )?;
+    fn hunk_range(hunk: &HunkDiff) -> HunkRange {
+        HunkRange { old_start: hunk.old_start, old_lines: hunk.old_lines, new_start: hunk.new_start, new_lines: hunk.new_lines }
+    }
 
-    // Process the diff to collect file changes
-    process_tree_diff(diff)
-        .map_err(|err| GitDiffError::Git(err))
-}
\ No newline at end of file
+    fn numbers(lines: &[LineDiff]) -> Vec<(Option<u32>, Option<u32>)> {
+        lines.iter().map(|line| (line.old_lineno, line.new_lineno)).collect()
+    }
+
+    // A file of 20 lines with a line inserted after line 10, and its single hunk without context
+    fn inserted_line() -> (TempDir, Repository, Vec<String>, HunkRange) {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 10 { format!("{}\ninserted", line) } else { line }));
+        let (dir, repo, commits) = repo_with_commits(&[&[("file.txt", &old)], &[("file.txt", &new)]]);
+
+        let settings = DiffSettings { context_lines: 0, ..DiffSettings::default() };
+        let diff = get_file_diff(&repo, &commits[0], &commits[1], "file.txt", &settings).unwrap();
+        let hunk = hunk_range(&diff.hunks[0]);
+        (dir, repo, commits, hunk)
+    }
+
+    fn context(
+        repo: &Repository,
+        commits: &[String],
+        hunk: &HunkRange,
+        direction: ContextDirection,
+        count: u32,
+    ) -> Vec<LineDiff> {
+        get_context_lines(repo, "file.txt", &commits[0], &commits[1], hunk, direction, count, &DiffSettings::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn reads_the_context_above_a_hunk() {
+        let (_dir, repo, commits, hunk) = inserted_line();
+
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Above, 3);
+        assert_eq!(numbers(&lines), vec![(Some(8), Some(8)), (Some(9), Some(9)), (Some(10), Some(10))]);
+        assert_eq!(lines[0].content, "line 8");
+        assert!(lines.iter().all(|line| line.origin == ' '));
+    }
+
+    #[test]
+    fn numbers_the_context_below_a_hunk_on_both_sides() {
+        let (_dir, repo, commits, hunk) = inserted_line();
+
+        // The inserted line shifts the new side by one
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Below, 2);
+        assert_eq!(numbers(&lines), vec![(Some(11), Some(12)), (Some(12), Some(13))]);
+        assert_eq!(lines[0].content, "line 11");
+    }
+
+    #[test]
+    fn stops_the_context_at_the_start_and_end_of_the_file() {
+        let (_dir, repo, commits, hunk) = inserted_line();
+
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Above, 100);
+        assert_eq!(lines.len(), 10);
+        assert_eq!(numbers(&lines)[0], (Some(1), Some(1)));
+
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Below, 100);
+        assert_eq!(lines.len(), 10);
+        assert_eq!(numbers(&lines)[9], (Some(20), Some(21)));
+    }
+
+    #[test]
+    fn saturates_counts_and_hunks_out_of_range() {
+        let (_dir, repo, commits, hunk) = inserted_line();
+
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Below, u32::MAX);
+        assert_eq!(lines.len(), 10);
+
+        let hunk = HunkRange { old_start: u32::MAX, old_lines: 0, new_start: u32::MAX, new_lines: u32::MAX };
+        assert!(context(&repo, &commits, &hunk, ContextDirection::Below, u32::MAX).is_empty());
+        assert!(context(&repo, &commits, &hunk, ContextDirection::Above, 2).is_empty());
+    }
+
+    #[test]
+    fn reads_the_context_of_deleted_files_from_the_old_side() {
+        let content = numbered_lines(5, |_, line| Some(line));
+        let (_dir, repo, commits) = repo_with_commits(&[&[("file.txt", &content), ("other.txt", "a\n")], &[("other.txt", "a\n")]]);
+
+        let hunk = HunkRange { old_start: 3, old_lines: 1, new_start: 0, new_lines: 0 };
+        // The file has no lines on the new side to number the context with
+        let lines = context(&repo, &commits, &hunk, ContextDirection::Above, 1);
+        assert_eq!(numbers(&lines), vec![(Some(2), None)]);
+        assert_eq!(lines[0].content, "line 2");
+    }
+
+    // The status, paths and similarity of each file changed between the first and last commits
+    fn summary(repo: &Repository, commits: &[String], settings: &DiffSettings) -> Vec<(char, String, String, Option<u16>)> {
+        get_tree_diff_summary(repo, &commits[0], commits.last().unwrap(), None, settings)
+            .unwrap()
+            .files
+            .into_iter()
+            .map(|file| (file.status, file.old_file, file.new_file, file.similarity))
+            .collect()
+    }
+
+    #[test]
+    fn detects_renames_with_their_similarity() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 5 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_commits(&[&[("old.txt", &old)], &[("new.txt", &new)]]);
+
+        let files = summary(&repo, &commits, &DiffSettings::default());
+        assert_eq!(files, vec![('R', "old.txt".to_string(), "new.txt".to_string(), Some(95))]);
+
+        let settings = DiffSettings { detect_renames: false, ..DiffSettings::default() };
+        let statuses: Vec<char> = summary(&repo, &commits, &settings).iter().map(|file| file.0).collect();
+        assert_eq!(statuses, vec!['A', 'D']);
+    }
+
+    #[test]
+    fn detects_copies_of_modified_files_when_enabled() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 20 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_commits(&[
+            &[("file.txt", &old)],
+            &[("file.txt", &new), ("copy.txt", &old)],
+        ]);
+
+        let files = summary(&repo, &commits, &DiffSettings::default());
+        assert_eq!(files[0], ('A', "copy.txt".to_string(), "copy.txt".to_string(), None));
+
+        let settings = DiffSettings { detect_copies: true, ..DiffSettings::default() };
+        let files = summary(&repo, &commits, &settings);
+        assert_eq!(files[0], ('C', "file.txt".to_string(), "copy.txt".to_string(), Some(100)));
+        assert_eq!(files[1].0, 'M');
+    }
+
+    #[test]
+    fn pairs_renames_only_above_the_similarity_threshold() {
+        // Half of the lines are replaced
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n % 2 == 0 { format!("changed {}", n) } else { line }));
+        let (_dir, repo, commits) = repo_with_commits(&[&[("old.txt", &old)], &[("new.txt", &new)]]);
+
+        let settings = DiffSettings { similarity_threshold: 30, ..DiffSettings::default() };
+        let files = summary(&repo, &commits, &settings);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, 'R');
+
+        let settings = DiffSettings { similarity_threshold: 90, ..DiffSettings::default() };
+        let statuses: Vec<char> = summary(&repo, &commits, &settings).iter().map(|file| file.0).collect();
+        assert_eq!(statuses, vec!['A', 'D']);
+    }
+
+    #[test]
+    fn leaves_the_similarity_of_changed_binary_files_unknown() {
+        let old: String = (0..2000).map(|n| char::from(b'a' + (n % 26) as u8)).collect::<String>() + "\0";
+        let new = old.replacen('a', "b", 1);
+        let (_dir, repo, commits) = repo_with_commits(&[&[("old.bin", &old)], &[("new.bin", &new)]]);
+
+        let files = get_tree_diff_summary(&repo, &commits[0], &commits[1], None, &DiffSettings::default()).unwrap().files;
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].status, 'R');
+        assert!(files[0].binary);
+        assert_eq!(files[0].similarity, None);
+    }
+
+    #[test]
+    fn finds_renamed_files_by_their_old_and_new_paths() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 5 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_commits(&[
+            &[("old.txt", &old), ("other.txt", "a\n")],
+            &[("new.txt", &new), ("other.txt", "b\n")],
+        ]);
+
+        for path in ["old.txt", "new.txt"] {
+            let diff = get_file_diff(&repo, &commits[0], &commits[1], path, &DiffSettings::default()).unwrap();
+            assert_eq!((diff.status, diff.old_file.as_str(), diff.new_file.as_str()), ('R', "old.txt", "new.txt"));
+            assert_eq!(diff.similarity, Some(95));
+            assert_eq!(diff.hunks.len(), 1);
+        }
+
+        // A new file reusing the old path is found first
+        let (_dir, repo, commits) = repo_with_commits(&[
+            &[("old.txt", &old)],
+            &[("new.txt", &new), ("old.txt", "unrelated\n")],
+        ]);
+        let diff = get_file_diff(&repo, &commits[0], &commits[1], "old.txt", &DiffSettings::default()).unwrap();
+        assert_eq!(diff.new_file, "old.txt");
+        assert_eq!(diff.status, 'M');
+    }
+
+    #[test]
+    fn counts_the_changed_lines_of_each_file_in_the_summary() {
+        let old = numbered_lines(10, |_, line| Some(line));
+        let edited = numbered_lines(10, |n, line| match n {
+            2 => None,
+            5 => Some("changed".to_string()),
+            _ => Some(line),
+        });
+        let (_dir, repo, commits) = repo_with_commits(&[
+            &[("a.txt", &old), ("b.bin", "\0binary"), ("c.txt", &old)],
+            &[("a.txt", &edited), ("b.bin", "\0changed"), ("d.txt", "new\nfile\n")],
+        ]);
+
+        let files = get_tree_diff_summary(&repo, &commits[0], &commits[1], None, &DiffSettings::default()).unwrap().files;
+        let counts: Vec<_> = files.iter()
+            .map(|file| (file.new_file.as_str(), file.status, file.binary, file.additions, file.deletions))
+            .collect();
+        assert_eq!(counts, vec![
+            ("a.txt", 'M', false, 1, 2),
+            ("b.bin", 'M', true, 0, 0),
+            ("c.txt", 'D', false, 0, 10),
+            ("d.txt", 'A', false, 2, 0),
+        ]);
+    }
+}
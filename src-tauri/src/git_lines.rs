@@ -1,8 +1,13 @@
-use git2::{DiffFormat, DiffOptions, Object, ObjectType, Repository};
+use git2::{DiffOptions, Object, ObjectType, Oid, Patch, Repository, Tree};
+use serde::Serialize;
 use std::io::{self};
 use std::ops::Range;
 use std::path::Path;
 use std::str;
+use std::sync::Arc;
+
+use crate::git_cache::{DiffKey, GitCache};
+use crate::highlight::{highlight_lines, HighlightSpan};
 
 /// Error type for file operations in a git repository
 #[derive(Debug)]
@@ -52,25 +57,44 @@ fn tree_to_treeish<'a>(
     Ok(tree)
 }
 
+// Id of the blob of a file in a tree, if the file exists there
+fn blob_id(tree: &Tree, file_path: &str) -> Option<Oid> {
+    tree.get_path(Path::new(file_path)).ok()
+        .filter(|entry| entry.kind() == Some(ObjectType::Blob))
+        .map(|entry| entry.id())
+}
+
+/// Lines of a file at a revision
+#[derive(Debug, Serialize)]
+pub struct FileLines {
+    pub lines: Vec<String>,
+    /// Syntax highlighting of each line, when requested and the language of the file is known
+    pub highlights: Option<Vec<Vec<HighlightSpan>>>,
+}
+
 /// Retrieves specific lines from a file at a specific Git revision using libgit2.
 ///
 /// # Arguments
 ///
 /// * `repo` - The Git repository
+/// * `cache` - Cache of the lines of files, shared by all the requests
 /// * `file_path` - Path to the file within the repository
 /// * `revision` - Git revision (commit hash, branch name, tag, etc.)
 /// * `line_range` - Range of lines to retrieve (1-based, inclusive start, inclusive end)
+/// * `highlight` - Whether to highlight the lines, which is done over the whole file
 ///
 /// # Returns
 ///
-/// * `Result<Vec<String>, GitFileError>` - The requested lines or an error
+/// * `Result<FileLines, GitFileError>` - The requested lines or an error
 ///
 pub fn get_file_lines_at_revision<'a>(
     repo: &Repository,
+    cache: &GitCache,
     file_path: &str,
     revision: &'a str,
     line_range: Range<usize>,
-) -> Result<Vec<String>, GitFileError<'a>> {
+    highlight: bool,
+) -> Result<FileLines, GitFileError<'a>> {
     // Validate the range
     if line_range.start < 1 || line_range.start > line_range.end {
         return Err(GitFileError::InvalidRange);
@@ -103,49 +127,51 @@ pub fn get_file_lines_at_revision<'a>(
         Err(data) => return Err(GitFileError::Generic(data.message().to_string())),
     };
 
-    // Get the object for the file
-    let blob = repo.find_blob(entry.id())?;
-
-    // Convert blob content to UTF-8 string
-    let content = match std::str::from_utf8(blob.content()) {
-        Ok(s) => s,
-        Err(_) => {
-            // If the file is not valid UTF-8, return an IO error
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "File content is not valid UTF-8",
-            ).into());
-        }
-    };
+    // Read the whole file once, as threads on the same file ask for its lines over and over
+    let file_lines = read_blob_lines(repo, cache, entry.id())?;
 
-    // Read the content line by line
-    let mut lines = Vec::new();
-    let mut line_num = 1;
-
-    for line in content.lines() {
-        // If we're past the end of our range, we can stop
-        if line_num > line_range.end {
-            break;
-        }
+    // Check if the range starts within the file
+    if line_range.start > file_lines.len() {
+        return Err(GitFileError::InvalidRange);
+    }
 
-        // If we're within our range, add the line
-        if line_num >= line_range.start {
-            lines.push(line.to_string());
-        }
+    let lines: Vec<String> = file_lines[line_range.start - 1..line_range.end.min(file_lines.len())].to_vec();
 
-        line_num += 1;
-    }
+    let highlights = if highlight {
+        highlight_lines(file_path, &file_lines.join("\n")).map(|highlights| {
+            highlights.into_iter()
+                .skip(line_range.start - 1)
+                .take(lines.len())
+                .collect()
+        })
+    } else {
+        None
+    };
 
-    // Check if we got any lines within the range
-    if lines.is_empty() && line_num <= line_range.start {
-        return Err(GitFileError::InvalidRange);
-    }
+    Ok(FileLines { lines, highlights })
+}
 
-    Ok(lines)
+// All the lines of a blob, read once and cached
+fn read_blob_lines<'a>(repo: &Repository, cache: &GitCache, id: Oid) -> Result<Arc<Vec<String>>, GitFileError<'a>> {
+    cache.blob_lines(id, || {
+        let blob = repo.find_blob(id)?;
+
+        // Convert blob content to UTF-8 string
+        match std::str::from_utf8(blob.content()) {
+            Ok(content) => Ok(content.lines().map(str::to_string).collect()),
+            Err(_) => {
+                // If the file is not valid UTF-8, return an IO error
+                Err(GitFileError::from(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "File content is not valid UTF-8",
+                )))
+            }
+        }
+    })
 }
 
 /// Struct to represent a diff line with context
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct DiffLineInfo {
     /// Original line number in the first revision
     pub old_lineno: Option<u32>,
@@ -155,14 +181,40 @@ pub struct DiffLineInfo {
     pub content: String,
     /// The origin of the line ('+' for addition, '-' for deletion, ' ' for context)
     pub origin: char,
+    /// Whether the line is within the requested range, rather than in the context around it
+    pub in_range: bool,
 }
 
+/// Retrieves the diff of a file between two Git revisions, limited to a range of lines of the file
+/// at `to_revision` (such as the right side of a PR thread).
+///
+/// The diff of the whole file is computed once and cached, so that all the threads on a file are
+/// served from the same diff.
+///
+/// # Arguments
+///
+/// * `repo` - The Git repository
+/// * `cache` - Cache of the diffs of files, shared by all the requests
+/// * `file_path` - Path to the file within the repository
+/// * `from_revision` - Git revision to diff from
+/// * `to_revision` - Git revision to diff to, which `line_range` refers to
+/// * `line_range` - Range of lines to include (1-based, inclusive start, inclusive end)
+/// * `context_lines` - Number of lines around the range to include as well
+///
+/// # Returns
+///
+/// * `Result<Vec<DiffLineInfo>, GitFileError>` - Added, deleted and context lines within the
+///   range, where deleted lines belong to the range if the line following them does. The file
+///   only needs to exist in one of the revisions.
+///
 pub fn get_file_diff_between_revisions<'a>(
     repo: &'a Repository,
+    cache: &GitCache,
     file_path: &str,
     from_revision: &'a str,
     to_revision: &'a str,
     line_range: Range<usize>,
+    context_lines: u32,
 ) -> Result<Vec<DiffLineInfo>, GitFileError<'a>> {
     // Validate the range
     if line_range.start < 1 || line_range.start > line_range.end {
@@ -180,169 +232,333 @@ pub fn get_file_diff_between_revisions<'a>(
     let to_tree_ref = to_tree.as_tree().ok_or_else(||
         GitFileError::Generic(format!("Failed to get tree for revision: {}", to_revision)))?;
 
-    // Set up diff options
+    // The range is on the new side (the right file of a thread), extended with context
+    let context_buffer = context_lines as usize;
+    let extended_start = line_range.start.saturating_sub(context_buffer).max(1);
+    let extended_end = line_range.end + context_buffer;
+    let in_extended_range = |line_num: usize| line_num >= extended_start && line_num <= extended_end;
+
+    let key = DiffKey {
+        file_path: file_path.to_string(),
+        old_blob: blob_id(from_tree_ref, file_path),
+        new_blob: blob_id(to_tree_ref, file_path),
+    };
+    let (old_blob, new_blob) = (key.old_blob, key.new_blob);
+
+    // An unchanged file has no diff, so the range is taken from its lines, all of them context
+    if old_blob == new_blob {
+        let Some(blob) = new_blob else {
+            return Err(GitFileError::FileNotFound);
+        };
+
+        let file_lines = read_blob_lines(repo, cache, blob)?;
+        let diff_lines = (extended_start..=extended_end.min(file_lines.len()))
+            .map(|line_num| DiffLineInfo {
+                old_lineno: Some(line_num as u32),
+                new_lineno: Some(line_num as u32),
+                // As in the diff, the content of the line includes its end
+                content: format!("{}\n", file_lines[line_num - 1]),
+                origin: ' ',
+                in_range: line_num >= line_range.start && line_num <= line_range.end,
+            })
+            .collect();
+        return Ok(diff_lines);
+    }
+
+    let file_diff = cache.file_diff(key, || diff_file(repo, file_path, old_blob, new_blob))?;
+
+    // Deleted lines have no new line number, so they are placed before the next line of the new
+    // side
+    let mut next_new_line = 1;
+    let diff_lines: Vec<DiffLineInfo> = file_diff.iter()
+        .filter_map(|line| {
+            // Determine whether to include this line based on its position on the new side
+            let position = match (line.origin, line.new_lineno) {
+                // Added and context lines - include if they're in the extended range
+                ('+' | ' ', Some(new_line)) => {
+                    next_new_line = new_line as usize + 1;
+                    new_line as usize
+                },
+
+                // Deleted lines - include if the place they were deleted from is in range
+                ('-', None) => next_new_line,
+
+                _ => return None,
+            };
+
+            in_extended_range(position).then(|| DiffLineInfo {
+                in_range: position >= line_range.start && position <= line_range.end,
+                ..line.clone()
+            })
+        })
+        .collect();
+
+    Ok(diff_lines)
+}
+
+// Diff two versions of a file over the whole file, so that any range of it can be taken from the
+// diff. A file that doesn't exist on one side is diffed against nothing.
+fn diff_file(
+    repo: &Repository,
+    file_path: &str,
+    old_blob: Option<Oid>,
+    new_blob: Option<Oid>,
+) -> Result<Vec<DiffLineInfo>, git2::Error> {
+    let old_blob = old_blob.map(|id| repo.find_blob(id)).transpose()?;
+    let new_blob = new_blob.map(|id| repo.find_blob(id)).transpose()?;
+    let old_content = old_blob.as_ref().map_or(&[][..], |blob| blob.content());
+    let new_content = new_blob.as_ref().map_or(&[][..], |blob| blob.content());
+
+    // With as much context as the file has lines, every line of it is part of a single hunk
+    let line_count = |content: &[u8]| content.iter().filter(|byte| **byte == b'\n').count() + 1;
+    let context_lines = line_count(old_content).max(line_count(new_content));
+
     let mut diff_opts = DiffOptions::new();
-    diff_opts.pathspec(file_path);
-    diff_opts.context_lines(3); // Default context lines
+    diff_opts.context_lines(u32::try_from(context_lines).unwrap_or(u32::MAX));
 
-    // Generate the diff between the two trees
-    let diff = repo.diff_tree_to_tree(
-        Some(from_tree_ref),
-        Some(to_tree_ref),
-        Some(&mut diff_opts),
-    )?;
+    let path = Path::new(file_path);
+    let patch = Patch::from_buffers(old_content, Some(path), new_content, Some(path), Some(&mut diff_opts))?;
 
     // Collect the diff lines
     let mut diff_lines = Vec::new();
 
-    // Precompute the extended range with context buffer
-    let context_buffer = 3;
-    let extended_start = if line_range.start > context_buffer {
-        line_range.start - context_buffer
-    } else {
-        1
-    };
-    let extended_end = line_range.end + context_buffer;
+    for hunk_index in 0..patch.num_hunks() {
+        let line_count = patch.num_lines_in_hunk(hunk_index)?;
 
-    // Use the diff.print callback to process each line
-    diff.print(DiffFormat::Patch, |delta, hunk, line| {
-        // Make sure we're only processing the file we're interested in
-        if let Some(path) = delta.new_file().path() {
-            if path.to_str() != Some(file_path) {
-                return true;
-            }
-        } else {
-            return true;
-        }
+        for line_index in 0..line_count {
+            let line = patch.line_in_hunk(hunk_index, line_index)?;
 
-        // Extract line information
-        let origin = line.origin();
-        let content = match str::from_utf8(line.content()) {
-            Ok(s) => s.to_string(),
-            Err(_) => "[Binary content]".to_string(),
-        };
+            // Special lines (end of file markers, etc.) are left out
+            let origin = line.origin();
+            if !matches!(origin, '+' | '-' | ' ') {
+                continue;
+            }
 
-        let old_lineno = line.old_lineno();
-        let new_lineno = line.new_lineno();
-
-        // Determine whether to include this line based on type and position
-        let include = match origin {
-            // Special lines (headers, etc.) - always exclude
-            c if c != '+' && c != '-' && c != ' ' => {
-                false
-            },
-
-            // Added lines - include if they're in the extended range
-            '+' => {
-                if let Some(new_line) = new_lineno {
-                    let line_num = new_line as usize;
-                    line_num >= extended_start && line_num <= extended_end
-                } else {
-                    false
-                }
-            },
-
-            // Deleted lines - include if they affect our extended range
-            '-' => {
-                if let Some(old_line) = old_lineno {
-                    // For deletions, we need to be more careful. If a line was deleted
-                    // that would have been in our range, we should show it.
-                    // Use the context of the current hunk to determine this.
-                    if let Some(ref hunk) = hunk {
-                        let hunk_old_start = hunk.old_start() as usize;
-                        let hunk_old_end = hunk_old_start + hunk.old_lines() as usize - 1;
-                        let hunk_new_start = hunk.new_start() as usize;
-                        let hunk_new_end = hunk_new_start + hunk.new_lines() as usize - 1;
-
-                        // Include if the hunk overlaps with our extended range
-                        !(hunk_new_end < extended_start || hunk_new_start > extended_end)
-                    } else {
-                        // Without hunk information, use the old line number directly
-                        let line_num = old_line as usize;
-                        line_num >= extended_start && line_num <= extended_end
-                    }
-                } else {
-                    false
-                }
-            },
-
-            // Context lines - include if they're in the extended range
-            ' ' => {
-                if let Some(new_line) = new_lineno {
-                    let line_num = new_line as usize;
-                    line_num >= extended_start && line_num <= extended_end
-                } else {
-                    false
-                }
-            },
-
-            // Unknown line type - exclude
-            _ => false,
-        };
+            let content = match str::from_utf8(line.content()) {
+                Ok(s) => s.to_string(),
+                Err(_) => "[Binary content]".to_string(),
+            };
 
-        if include {
+            // Whether the line is in range depends on the range taken from the diff
             diff_lines.push(DiffLineInfo {
-                old_lineno,
-                new_lineno,
+                old_lineno: line.old_lineno(),
+                new_lineno: line.new_lineno(),
                 content,
                 origin,
+                in_range: false,
             });
         }
+    }
 
-        true
-    })?;
+    Ok(diff_lines)
+}
 
-    // If we didn't get any lines, check if the file exists in both revisions
-    if diff_lines.is_empty() {
-        // Check if the file exists in the from_revision
-        match from_tree_ref.get_path(Path::new(file_path)) {
-            Ok(_) => {}
-            Err(_) => return Err(GitFileError::FileNotFound),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Oid, Signature};
+    use tempfile::TempDir;
+
+    // Create a repository with one commit per version of `file.txt`
+    fn repo_with_versions(versions: &[&str]) -> (TempDir, Repository, Vec<Oid>) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+
+        let mut commits = Vec::new();
+        for (index, content) in versions.iter().enumerate() {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            let mut builder = repo.treebuilder(None).unwrap();
+            builder.insert("file.txt", blob, 0o100644).unwrap();
+            let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+
+            let parents = commits.last().map(|id| repo.find_commit(*id).unwrap());
+            let parents: Vec<_> = parents.iter().collect();
+            let message = format!("Version {}", index);
+            let commit = repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &parents).unwrap();
+            commits.push(commit);
         }
 
-        // Check if the file exists in the to_revision
-        match to_tree_ref.get_path(Path::new(file_path)) {
-            Ok(_) => {}
-            Err(_) => return Err(GitFileError::FileNotFound),
-        }
+        (dir, repo, commits)
+    }
 
-        // If the file exists in both revisions but there's no diff in the specified range,
-        // it means there were no changes in that range
+    // Lines "line 1" to "line `count`", with `edit` applied to each (line number, line)
+    fn numbered_lines(count: usize, edit: impl Fn(usize, String) -> Option<String>) -> String {
+        (1..=count)
+            .filter_map(|n| edit(n, format!("line {}", n)))
+            .map(|line| line + "\n")
+            .collect()
     }
 
-    Ok(diff_lines)
-}
+    fn diff(repo: &Repository, from: Oid, to: Oid, range: Range<usize>, context: u32) -> Vec<DiffLineInfo> {
+        let (from, to) = (from.to_string(), to.to_string());
+        get_file_diff_between_revisions(repo, &GitCache::default(), "file.txt", &from, &to, range, context).unwrap()
+    }
 
-/// A more simplified version that returns just the unified diff as a string
-pub fn get_file_diff_as_strings<'a>(
-    repo: &'a Repository,
-    file_path: &str,
-    from_revision: &'a str,
-    to_revision: &'a str,
-    line_range: Range<usize>,
-) -> Result<String, GitFileError<'a>> {
-    let diff_lines = get_file_diff_between_revisions(repo, file_path, from_revision, to_revision, line_range)?;
-
-    let mut result = String::new();
-    for line in diff_lines {
-        match line.origin {
-            '+' | '-' | ' ' => {
-                result.push(line.origin);
-                result.push_str(&line.new_lineno.or(line.old_lineno).map(|lineno| lineno.to_string()).unwrap_or_else(|| "?".to_string()));
-                result.push_str(&line.content);
-                if !line.content.ends_with('\n') {
-                    result.push('\n');
-                }
-            },
-            _ => {
-                // Handle diff header lines and other special lines
-                result.push_str(&line.content);
-                if !line.content.ends_with('\n') {
-                    result.push('\n');
-                }
-            }
+    fn origins(lines: &[DiffLineInfo]) -> Vec<(char, Option<u32>, Option<u32>)> {
+        lines.iter().map(|line| (line.origin, line.old_lineno, line.new_lineno)).collect()
+    }
+
+    #[test]
+    fn excludes_deletions_outside_the_range_in_the_same_hunk() {
+        // Lines 8 and 12 change, so the default context merges them into a single hunk
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| if n == 8 || n == 12 { None } else { Some(line) });
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        let lines = diff(&repo, commits[0], commits[1], 9..9, 0);
+
+        // Only the line itself, without the deletions around it
+        assert_eq!(origins(&lines), vec![(' ', Some(10), Some(9))]);
+    }
+
+    #[test]
+    fn includes_deletions_anchored_in_the_range() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| if n == 10 { None } else { Some(line) });
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        // "line 10" was deleted right before "line 11", which is new line 10
+        let lines = diff(&repo, commits[0], commits[1], 10..10, 0);
+        assert_eq!(origins(&lines), vec![('-', Some(10), None), (' ', Some(11), Some(10))]);
+
+        // and after "line 9", so it isn't part of a range ending there
+        let lines = diff(&repo, commits[0], commits[1], 9..9, 0);
+        assert_eq!(origins(&lines), vec![(' ', Some(9), Some(9))]);
+    }
+
+    #[test]
+    fn maps_the_range_to_the_new_side_after_insertions() {
+        // Three lines inserted at the top shift the rest of the file down
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = format!("a\nb\nc\n{}", old);
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        let lines = diff(&repo, commits[0], commits[1], 2..4, 0);
+        assert_eq!(
+            origins(&lines),
+            vec![('+', None, Some(2)), ('+', None, Some(3)), (' ', Some(1), Some(4))]
+        );
+        assert_eq!(lines[2].content, "line 1\n");
+    }
+
+    #[test]
+    fn includes_replaced_lines_with_context() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 10 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        let lines = diff(&repo, commits[0], commits[1], 10..10, 1);
+        assert_eq!(
+            origins(&lines),
+            vec![
+                (' ', Some(9), Some(9)),
+                ('-', Some(10), None),
+                ('+', None, Some(10)),
+                (' ', Some(11), Some(11)),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_the_lines_of_the_range_apart_from_context() {
+        let old = numbered_lines(20, |_, line| Some(line));
+        let new = numbered_lines(20, |n, line| Some(if n == 10 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        // The deleted line belongs to the range, as the line following it does
+        let lines = diff(&repo, commits[0], commits[1], 10..10, 1);
+        let in_range: Vec<bool> = lines.iter().map(|line| line.in_range).collect();
+        assert_eq!(in_range, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn includes_unchanged_lines_far_from_changes() {
+        let old = numbered_lines(40, |_, line| Some(line));
+        let new = numbered_lines(40, |n, line| if n == 2 { None } else { Some(line) });
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        let lines = diff(&repo, commits[0], commits[1], 30..31, 0);
+        assert_eq!(origins(&lines), vec![(' ', Some(31), Some(30)), (' ', Some(32), Some(31))]);
+    }
+
+    #[test]
+    fn includes_deletions_at_the_end_of_the_file() {
+        let old = numbered_lines(10, |_, line| Some(line));
+        let new = numbered_lines(10, |n, line| if n > 8 { None } else { Some(line) });
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        // Lines deleted at the end are placed after the last line
+        let lines = diff(&repo, commits[0], commits[1], 9..9, 0);
+        assert_eq!(origins(&lines), vec![('-', Some(9), None), ('-', Some(10), None)]);
+    }
+
+    #[test]
+    fn serves_ranges_of_a_file_from_one_cached_diff() {
+        let old = numbered_lines(40, |_, line| Some(line));
+        let new = numbered_lines(40, |n, line| if n % 10 == 0 { None } else { Some(line) });
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+        let (from, to) = (commits[0].to_string(), commits[1].to_string());
+
+        let cache = GitCache::default();
+        for range in [1..3, 9..9, 20..30, 35..36] {
+            let cached = get_file_diff_between_revisions(&repo, &cache, "file.txt", &from, &to, range.clone(), 1).unwrap();
+            assert_eq!(origins(&cached), origins(&diff(&repo, commits[0], commits[1], range, 1)));
         }
     }
 
-    Ok(result)
-}
\ No newline at end of file
+    #[test]
+    fn rejects_invalid_ranges() {
+        let (_dir, repo, commits) = repo_with_versions(&["a\n", "b\n"]);
+        let (from, to) = (commits[0].to_string(), commits[1].to_string());
+
+        let result = get_file_diff_between_revisions(&repo, &GitCache::default(), "file.txt", &from, &to, 0..1, 3);
+        assert!(matches!(result, Err(GitFileError::InvalidRange)));
+
+        let (start, end) = (5, 2);
+        let result = get_file_diff_between_revisions(&repo, &GitCache::default(), "file.txt", &from, &to, start..end, 3);
+        assert!(matches!(result, Err(GitFileError::InvalidRange)));
+    }
+
+    #[test]
+    fn includes_the_range_of_unchanged_files_as_context() {
+        let content = numbered_lines(20, |_, line| Some(line));
+        let (_dir, repo, commits) = repo_with_versions(&[&content, &content]);
+
+        let lines = diff(&repo, commits[0], commits[1], 10..11, 1);
+        assert_eq!(
+            origins(&lines),
+            vec![
+                (' ', Some(9), Some(9)),
+                (' ', Some(10), Some(10)),
+                (' ', Some(11), Some(11)),
+                (' ', Some(12), Some(12)),
+            ]
+        );
+        assert_eq!(lines[1].content, "line 10\n");
+        let in_range: Vec<bool> = lines.iter().map(|line| line.in_range).collect();
+        assert_eq!(in_range, vec![false, true, true, false]);
+
+        // The context stops at the end of the file
+        let lines = diff(&repo, commits[0], commits[1], 20..20, 3);
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn returns_no_lines_past_the_end_of_a_changed_file() {
+        let old = numbered_lines(10, |_, line| Some(line));
+        let new = numbered_lines(10, |n, line| Some(if n == 1 { "changed".to_string() } else { line }));
+        let (_dir, repo, commits) = repo_with_versions(&[&old, &new]);
+
+        assert!(diff(&repo, commits[0], commits[1], 30..30, 0).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_files() {
+        let (_dir, repo, commits) = repo_with_versions(&["a\n", "a\n"]);
+        let (from, to) = (commits[0].to_string(), commits[1].to_string());
+
+        let result = get_file_diff_between_revisions(&repo, &GitCache::default(), "missing.txt", &from, &to, 1..1, 3);
+        assert!(matches!(result, Err(GitFileError::FileNotFound)));
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use syntect::easy::ScopeRangeIterator;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+
+// Larger files are left unhighlighted, as parsing them would take seconds
+const MAX_HIGHLIGHT_BYTES: usize = 1024 * 1024;
+
+/// The kind of a highlighted token, named after the token types of Prism so that the frontend
+/// themes apply to it as they are
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenKind {
+    Comment,
+    String,
+    Keyword,
+    Operator,
+    Number,
+    Boolean,
+    Constant,
+    Function,
+    ClassName,
+    Tag,
+    AttrName,
+    Punctuation,
+}
+
+/// A highlighted token of a line, in UTF-16 code units as used by JavaScript strings. Text not
+/// covered by a span is plain.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+struct Highlighter {
+    syntaxes: SyntaxSet,
+    // Scope prefixes mapped to token kinds, the more specific prefixes first
+    kinds: Vec<(Scope, TokenKind)>,
+    comment: Scope,
+    string: Scope,
+}
+
+// Loading the bundled syntaxes takes a while, so it is only done on first use
+fn highlighter() -> &'static Highlighter {
+    static HIGHLIGHTER: OnceLock<Highlighter> = OnceLock::new();
+
+    HIGHLIGHTER.get_or_init(|| {
+        let start = Instant::now();
+        let scope = |name: &str| Scope::new(name).expect("valid scope name");
+
+        let kinds = [
+            ("constant.numeric", TokenKind::Number),
+            ("constant.language", TokenKind::Boolean),
+            ("constant", TokenKind::Constant),
+            ("keyword.operator", TokenKind::Operator),
+            ("keyword", TokenKind::Keyword),
+            ("storage", TokenKind::Keyword),
+            ("variable.language", TokenKind::Keyword),
+            ("entity.name.function", TokenKind::Function),
+            ("support.function", TokenKind::Function),
+            ("variable.function", TokenKind::Function),
+            ("entity.name.tag", TokenKind::Tag),
+            ("entity.other.attribute-name", TokenKind::AttrName),
+            ("entity.name", TokenKind::ClassName),
+            ("entity.other.inherited-class", TokenKind::ClassName),
+            ("support.type", TokenKind::ClassName),
+            ("support.class", TokenKind::ClassName),
+            ("punctuation", TokenKind::Punctuation),
+        ]
+        .into_iter()
+        .map(|(name, kind)| (scope(name), kind))
+        .collect();
+
+        let highlighter = Highlighter {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            kinds,
+            comment: scope("comment"),
+            string: scope("string"),
+        };
+
+        println!("Performance: loaded {} syntaxes in {:?}",
+                 highlighter.syntaxes.syntaxes().len(), start.elapsed());
+        highlighter
+    })
+}
+
+impl Highlighter {
+    // Pick the syntax from the file name or extension, falling back to the first line
+    // (shebangs, XML declarations, etc.)
+    fn find_syntax(&self, path: &str, content: &str) -> Option<&SyntaxReference> {
+        let path = Path::new(path);
+        let by_name = || path.file_name()?.to_str().and_then(|name| self.syntaxes.find_syntax_by_extension(name));
+        let by_extension = || path.extension()?.to_str().and_then(|ext| self.syntaxes.find_syntax_by_extension(ext));
+        let by_first_line = || self.syntaxes.find_syntax_by_first_line(content.lines().next()?);
+
+        by_name().or_else(by_extension).or_else(by_first_line)
+    }
+
+    // Comments and strings color everything within them, punctuation included. Otherwise the
+    // innermost scope with a known kind decides.
+    fn token_kind(&self, scopes: &[Scope]) -> Option<TokenKind> {
+        if scopes.iter().any(|scope| self.comment.is_prefix_of(*scope)) {
+            return Some(TokenKind::Comment);
+        }
+        if scopes.iter().any(|scope| self.string.is_prefix_of(*scope)) {
+            return Some(TokenKind::String);
+        }
+
+        scopes.iter().rev().find_map(|scope| {
+            self.kinds.iter()
+                .find(|(prefix, _)| prefix.is_prefix_of(*scope))
+                .map(|(_, kind)| *kind)
+        })
+    }
+}
+
+/// Highlight a whole file, so that constructs spanning lines (block comments, multi-line
+/// strings) are highlighted correctly on every line. The language is detected from the path.
+///
+/// Returns the spans of each line, in the order of `content.lines()`, or `None` if the language
+/// is unknown or the file is too large.
+pub fn highlight_lines(path: &str, content: &str) -> Option<Vec<Vec<HighlightSpan>>> {
+    if content.len() > MAX_HIGHLIGHT_BYTES {
+        return None;
+    }
+
+    let highlighter = highlighter();
+    let syntax = highlighter.find_syntax(path, content)?;
+
+    let start = Instant::now();
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+
+    for line in content.split_inclusive('\n') {
+        let ops = match state.parse_line(line, &highlighter.syntaxes) {
+            Ok(ops) => ops,
+            Err(e) => {
+                println!("Failed to highlight {}: {}", path, e);
+                return None;
+            }
+        };
+
+        let mut spans: Vec<HighlightSpan> = Vec::new();
+        let mut utf16_offset = 0;
+
+        // The ranges follow each other over the whole line, each with the scopes applied so far
+        for (range, op) in ScopeRangeIterator::new(&ops, line) {
+            if let Err(e) = stack.apply(op) {
+                println!("Failed to highlight {}: {}", path, e);
+                return None;
+            }
+            if range.is_empty() {
+                continue;
+            }
+
+            let start = utf16_offset;
+            utf16_offset += line[range].encode_utf16().count();
+
+            let Some(kind) = highlighter.token_kind(stack.as_slice()) else {
+                continue;
+            };
+            match spans.last_mut() {
+                Some(last) if last.end == start && last.kind == kind => last.end = utf16_offset,
+                _ => spans.push(HighlightSpan { start, end: utf16_offset, kind }),
+            }
+        }
+
+        // Line endings are not part of the returned lines
+        let line_length = line.trim_end_matches(['\n', '\r']).encode_utf16().count();
+        spans.retain_mut(|span| {
+            span.end = span.end.min(line_length);
+            span.start < span.end
+        });
+
+        lines.push(spans);
+    }
+
+    println!("Performance: highlighted {} lines of {} as {} in {:?}",
+             lines.len(), path, syntax.name, start.elapsed());
+
+    Some(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(start: usize, end: usize, kind: TokenKind) -> HighlightSpan {
+        HighlightSpan { start, end, kind }
+    }
+
+    #[test]
+    fn carries_block_comments_across_lines() {
+        let content = "let a = 1; /* start\nstill a comment\nend */ let b = 2;\n";
+        let lines = highlight_lines("main.rs", content).unwrap();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].last(), Some(&span(11, 19, TokenKind::Comment)));
+        assert_eq!(lines[1], vec![span(0, 15, TokenKind::Comment)]);
+        assert_eq!(lines[2][0], span(0, 6, TokenKind::Comment));
+        assert!(lines[2].contains(&span(7, 10, TokenKind::Keyword)));
+    }
+
+    #[test]
+    fn carries_strings_across_lines() {
+        let content = "const TEXT = `first\nsecond`;\nlet x = 1;\n";
+        let lines = highlight_lines("main.js", content).unwrap();
+
+        assert_eq!(lines[1][0], span(0, 7, TokenKind::String));
+        assert!(!lines[2].iter().any(|span| span.kind == TokenKind::String));
+    }
+
+    #[test]
+    fn counts_characters_outside_the_bmp_as_two_code_units() {
+        // The emoji is a surrogate pair in JavaScript strings, so the string ends at 12
+        let lines = highlight_lines("main.rs", "let s = \"😀\"; let n = 1;\n").unwrap();
+
+        assert!(lines[0].contains(&span(8, 12, TokenKind::String)));
+        assert!(lines[0].contains(&span(22, 23, TokenKind::Number)));
+    }
+
+    #[test]
+    fn leaves_line_endings_out_of_the_spans() {
+        let lines = highlight_lines("main.rs", "// comment\r\nlet a = 1;").unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], vec![span(0, 10, TokenKind::Comment)]);
+    }
+
+    #[test]
+    fn detects_the_language_from_the_first_line() {
+        assert!(highlight_lines("build", "#!/bin/bash\necho hello\n").is_some());
+    }
+
+    #[test]
+    fn skips_unknown_languages_and_large_files() {
+        assert!(highlight_lines("notes.unknown-extension", "just some text\n").is_none());
+
+        let large = "let a = 1;\n".repeat(MAX_HIGHLIGHT_BYTES / 11 + 1);
+        assert!(large.len() > MAX_HIGHLIGHT_BYTES);
+        assert!(highlight_lines("main.rs", &large).is_none());
+    }
+}
@@ -3,17 +3,41 @@ mod search;
 mod git_lines;
 mod git_commit;
 mod git_diff;
-
-use std::{sync::Arc, time::Instant};
+mod pr_query;
+mod pr_document;
+mod commit_index;
+mod git_blame;
+mod word_diff;
+mod highlight;
+mod missing_commits;
+mod repo_pool;
+mod git_cache;
+mod thread_snippets;
+
+use std::{collections::HashMap, path::Path, sync::RwLock, thread, time::Instant};
+use chrono::{DateTime, Utc};
 use git2::Repository;
-use git_commit::{get_commit_metadata, CommitMetadata};
-use git_diff::{get_tree_diff_between_revisions, get_filtered_tree_diff, TreeDiff};
-use git_lines::{get_file_diff_as_strings, get_file_lines_at_revision};
+use git_commit::{get_commit_metadata, get_pr_commit_series, CommitMetadata};
+use git_diff::{
+    get_context_lines, get_file_diff, get_merge_base, get_tree_diff_between_revisions, get_tree_diff_summary, get_filtered_tree_diff,
+    ContextDirection, DiffSettings, FileDiff, HunkRange, LineDiff, TreeDiff, TreeDiffSummary,
+};
+use git_lines::{get_file_diff_between_revisions, get_file_lines_at_revision, DiffLineInfo, FileLines};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tauri::{async_runtime::Mutex, http::HeaderValue, Manager, State, UriSchemeContext, Wry};
+use tauri::{http::HeaderValue, AppHandle, Emitter, Manager, State, UriSchemeContext, Wry};
 use zip_filesystem::FileSystem;
-use search::SearchIndex;
+use search::{PrSearchDetails, SearchIndex, TokenizerOptions};
+use pr_query::{parse_query, PrFilter, PrSort};
+use pr_document::{parse_pr_document, IterationDetails, PrDocument, PrDocumentError};
+use commit_index::{CommitIndex, PrCommitRefs};
+pub use commit_index::{CommitRole, PrCommitMatch};
+pub use missing_commits::{MissingCommit, MissingCommitsReport};
+use missing_commits::{FetchProgress, FetchReport};
+use git_blame::{blame_range, BlameLine};
+use repo_pool::RepoPool;
+use git_cache::GitCache;
+use thread_snippets::ThreadSnippet;
 
 #[tauri::command(async)]
 fn greet(name: &str) -> String {
@@ -30,21 +54,90 @@ struct PrFile {
     title: String,
     author: String,
     status: String,
-    creation_date: String,
+    creation_date: DateTime<Utc>,
+    completion_date: Option<DateTime<Utc>>,
     source_branch: String,
     target_branch: String,
+    reviewers: Vec<PrReviewer>,
+    description: Option<String>,
+    is_draft: Option<bool>,
+    repository: Option<String>,
+    reviewer_count: Option<u32>,
+    has_conflicts: Option<bool>,
+    work_item_count: Option<u32>,
+    thread_count: Option<u32>,
+}
+
+/// A reviewer of a PR along with their final vote, as written to the full PR document
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PrReviewer {
+    id: String,
+    display_name: String,
+    /// 10=approved, 5=approved with suggestions, 0=no vote, -5=waiting for author, -10=rejected
+    vote: i32,
+    #[serde(default)]
+    is_required: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct PrIndexEntry {
     id: i32,
     title: String,
+    #[serde(default)]
+    description: Option<String>,
     created_by: String,
-    creation_date: String,
+    creation_date: DateTime<Utc>,
+    #[serde(default)]
+    completion_date: Option<DateTime<Utc>>,
     status: String,
     source_branch: String,
     target_branch: String,
     filename: String,
+    // The fields below are optional, as older archives were exported without them
+    #[serde(default)]
+    is_draft: Option<bool>,
+    #[serde(default)]
+    repository: Option<String>,
+    #[serde(default)]
+    reviewer_count: Option<u32>,
+    #[serde(default)]
+    has_conflicts: Option<bool>,
+    #[serde(default)]
+    work_item_count: Option<u32>,
+    #[serde(default)]
+    thread_count: Option<u32>,
+    // Not part of the index file; attached from the full PR document when the search index is built
+    #[serde(default)]
+    reviewers: Vec<PrReviewer>,
+}
+
+// The changes of a PR as shown in Azure DevOps' "Files" tab
+#[derive(Debug, Serialize)]
+struct PrDiff {
+    base_commit: String,
+    source_commit: String,
+    // Whether the base is the common ref commit recorded in the archive, or computed locally
+    base_from_archive: bool,
+    #[serde(flatten)]
+    diff: TreeDiff,
+}
+
+// A range of lines of a file to diff between two revisions, as commented on by a thread
+#[derive(Debug, Deserialize)]
+struct FileDiffRequest {
+    file_path: String,
+    from_revision: String,
+    to_revision: String,
+    start_line: usize,
+    end_line: usize,
+}
+
+// The diff of a requested range, or why it couldn't be computed
+#[derive(Debug, Serialize)]
+struct FileDiffResult {
+    diff: Option<Vec<DiffLineInfo>>,
+    error: Option<String>,
 }
 
 // The index is just an array of entries
@@ -54,7 +147,10 @@ struct AppState {
     fs: FileSystem,                          // Main archive for PR data
     images_fs: FileSystem,                   // Separate archive for images
     search: SearchIndex,
-    repo: Arc<Mutex<Option<Repository>>>,
+    commits: CommitIndex,
+    diff_settings: RwLock<DiffSettings>,
+    repos: RepoPool,
+    git_cache: GitCache,
 }
 
 // Helper function to convert index entries to PrFile objects
@@ -72,32 +168,160 @@ fn index_entries_to_pr_files(entries: Vec<PrIndexEntry>) -> Vec<PrFile> {
             author: entry.created_by,
             status: entry.status,
             creation_date: entry.creation_date,
+            completion_date: entry.completion_date,
             source_branch: entry.source_branch,
             target_branch: entry.target_branch,
+            reviewers: entry.reviewers,
+            description: entry.description,
+            is_draft: entry.is_draft,
+            repository: entry.repository,
+            reviewer_count: entry.reviewer_count,
+            has_conflicts: entry.has_conflicts,
+            work_item_count: entry.work_item_count,
+            thread_count: entry.thread_count,
         });
     }
 
     files
 }
 
+// Helper function to collect the searchable details (reviewers, comments) of every PR
+// document in the archive, keyed by PR number
+fn load_pr_search_details(fs: &FileSystem) -> Result<HashMap<i32, PrSearchDetails>, String> {
+    let mut details = HashMap::new();
+
+    fs.for_each_file_from_memory("prs/", |path, content| {
+        match serde_json::from_str::<PrSearchDetails>(content) {
+            Ok(pr_details) => {
+                details.insert(pr_details.id, pr_details);
+            },
+            Err(e) => println!("Skipping search details of '{}': {}", path, e),
+        }
+    })?;
+
+    Ok(details)
+}
+
+// Helper function to read the entries of the archive's index file
+fn read_index_entries(fs: &FileSystem) -> Result<PrIndex, String> {
+    let index_content = fs.get_index_content()?;
+    fs.parse_json::<PrIndex>(&index_content)
+}
+
+// Helper function to build the search index from the archive if it isn't already, or wait for
+// the build in progress
+fn ensure_search_index(state: &AppState) -> Result<(), String> {
+    state.search.ensure_built(|| {
+        let index_entries = read_index_entries(&state.fs)?;
+        let details = load_pr_search_details(&state.fs)?;
+        Ok((index_entries, details))
+    })
+}
+
+// Helper function to load the PR entries of the archive if they aren't already, without
+// building the search index
+fn ensure_index_entries(state: &AppState) -> Result<(), String> {
+    state.search.load_entries(|| read_index_entries(&state.fs))
+}
+
+// Helper function to get the PR entries of the archive, loading them if needed
+fn load_index_entries(state: &AppState) -> Result<PrIndex, String> {
+    ensure_index_entries(state)?;
+    Ok(state.search.entries())
+}
+
+// Helper function to build the search index on another thread, as it parses every PR document
+// of the archive. Searches wait for the build instead of starting their own.
+fn build_search_index_in_background(app: &AppHandle) {
+    let app = app.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = ensure_search_index(&app.state::<AppState>()) {
+            println!("Failed to build the search index: {}", e);
+        }
+    });
+}
+
+// Helper function to read a file from the PR archive, preferring the in-memory copy
+fn read_archive_file(fs: &FileSystem, path: &str) -> Result<String, String> {
+    match fs.read_file_from_memory(path) {
+        Ok(content) => Ok(content),
+        // Fall back to extracting and reading the file
+        Err(_) => fs.read_file(path)
+    }
+}
+
+// Helper function to collect the commit references of every PR document in the archive
+fn load_pr_commit_refs(fs: &FileSystem) -> Result<Vec<PrCommitRefs>, String> {
+    let mut refs = Vec::new();
+
+    fs.for_each_file_from_memory("prs/", |path, content| {
+        match serde_json::from_str::<PrCommitRefs>(content) {
+            Ok(pr_refs) => refs.push(pr_refs),
+            Err(e) => println!("Skipping commit references of '{}': {}", path, e),
+        }
+    })?;
+
+    Ok(refs)
+}
+
+// Helper function to build the commit index from the archive if it isn't already
+fn ensure_commit_index(state: &AppState) -> Result<(), String> {
+    if !state.commits.is_initialized() {
+        state.commits.rebuild_index(load_pr_commit_refs(&state.fs)?);
+    }
+
+    Ok(())
+}
+
+// Helper function to resolve a revision to the id of the commit it points to
+fn resolve_commit_id(repo: &Repository, revision: &str) -> Result<git2::Oid, String> {
+    repo.revparse_single(revision)
+        .and_then(|obj| obj.peel_to_commit())
+        .map(|commit| commit.id())
+        .map_err(|_| format!("Revision not found: {}", revision))
+}
+
+// Helper function to read and validate the full document of a PR
+fn read_pr_document(state: &AppState, pr_number: i32) -> Result<PrDocument, PrDocumentError> {
+    ensure_index_entries(state)?;
+
+    let entry = state.search.entry(pr_number)
+        .ok_or(PrDocumentError::NotFound(pr_number))?;
+    let path = format!("prs/{}", entry.filename);
+    let content = read_archive_file(&state.fs, &path)?;
+
+    parse_pr_document(&path, &content, pr_number)
+}
+
 #[tauri::command(async)]
-fn get_pr_files(state: State<AppState>) -> Result<Vec<PrFile>, String> {
+fn get_pr_files(
+    filter: Option<PrFilter>,
+    sort: Option<PrSort>,
+    app: AppHandle,
+    state: State<AppState>
+) -> Result<Vec<PrFile>, String> {
     let start = Instant::now();
 
-    // Get and parse the index
-    let index_content = state.fs.get_index_content()?;
-    let index_entries = state.fs.parse_json::<PrIndex>(&index_content)?;
+    // Reviewers are attached to the entries by the search index, so filtering on them needs it
+    // built. Otherwise the PRs are listed from the index file right away, and the search index
+    // is built meanwhile.
+    if filter.as_ref().is_some_and(PrFilter::has_reviewer_criteria) {
+        ensure_search_index(&state)?;
+    } else if !state.search.is_initialized() {
+        build_search_index_in_background(&app);
+    }
+    let mut index_entries = load_index_entries(&state)?;
 
-    // Rebuild search index if needed
-    if !state.search.is_initialized() {
-        state.search.rebuild_index(&index_entries)?;
+    if let Some(filter) = filter {
+        let now = Utc::now();
+        index_entries.retain(|entry| filter.matches(entry, now));
     }
 
-    // Create files from index using the extracted function
-    let mut files = index_entries_to_pr_files(index_entries);
+    // Sort by PR number descending unless asked otherwise
+    sort.unwrap_or_default().apply(&mut index_entries, Utc::now());
 
-    // Sort by PR number descending
-    files.sort_by(|a, b| b.pr_number.cmp(&a.pr_number));
+    // Create files from index using the extracted function
+    let files = index_entries_to_pr_files(index_entries);
 
     println!("Performance: get_pr_files successful with {} files in {:?}",
              files.len(), start.elapsed());
@@ -106,19 +330,30 @@ fn get_pr_files(state: State<AppState>) -> Result<Vec<PrFile>, String> {
 }
 
 #[tauri::command(async)]
-fn search_prs(query: String, state: State<AppState>) -> Result<Vec<PrFile>, String> {
+fn search_prs(
+    query: String,
+    filter: Option<PrFilter>,
+    sort: Option<PrSort>,
+    state: State<AppState>
+) -> Result<Vec<PrFile>, String> {
     let start = Instant::now();
 
     // Make sure we have an initialized search index
-    if !state.search.is_initialized() {
-        // Get and parse the index to build the search index
-        let index_content = state.fs.get_index_content()?;
-        let index_entries = state.fs.parse_json::<PrIndex>(&index_content)?;
-        state.search.rebuild_index(&index_entries)?;
-    }
+    ensure_search_index(&state)?;
+
+    // Split `key:value` qualifiers (reviewer, vote, created, sort, ...) from the free text
+    let parsed = parse_query(&query);
+    let filter = filter.unwrap_or_default();
 
     // Search for matching PRs
-    let results = state.search.search(&query)?;
+    let now = Utc::now();
+    let mut results = state.search.search(&parsed.text)?;
+    results.retain(|entry| parsed.filter.matches(entry, now) && filter.matches(entry, now));
+
+    // Results stay in relevance order unless a sort was requested
+    if let Some(sort) = parsed.sort.or(sort) {
+        sort.apply(&mut results, now);
+    }
 
     // Create files from search results using the extracted function
     let files = index_entries_to_pr_files(results);
@@ -138,13 +373,25 @@ fn set_archive_file(new_archive: String, state: State<AppState>) -> Result<(), S
 
     // Clear the search index to force rebuild on next search
     if result.is_ok() {
-        // We'll rebuild the search index when needed
+        state.search.clear();
+        state.commits.clear();
     }
 
     println!("Performance: set_archive_file completed in {:?}", start.elapsed());
     result
 }
 
+#[tauri::command(async)]
+fn get_search_tokenizer_options(state: State<AppState>) -> TokenizerOptions {
+    state.search.tokenizer_options()
+}
+
+#[tauri::command(async)]
+fn set_search_tokenizer_options(options: TokenizerOptions, state: State<AppState>) {
+    // The search index is rebuilt with the new options on next search
+    state.search.set_tokenizer_options(options);
+}
+
 #[tauri::command(async)]
 fn set_images_archive_file(new_archive: String, state: State<AppState>) -> Result<(), String> {
     let start = Instant::now();
@@ -173,11 +420,160 @@ async fn get_images_archive_path(
 #[tauri::command(async)]
 fn read_pr_file(path: String, state: State<AppState>) -> Result<String, String> {
     // For better performance, try to read directly from memory if possible
-    match state.fs.read_file_from_memory(&path) {
-        Ok(content) => Ok(content),
-        // Fall back to extracting and reading the file
-        Err(_) => state.fs.read_file(&path)
+    read_archive_file(&state.fs, &path)
+}
+
+// Helper function to resolve the commits to diff between two iterations of a PR. Iteration 0
+// stands for the PR's base, as in Azure DevOps, which is the common ref commit of the later one
+fn iteration_diff_commits(
+    document: &PrDocument,
+    from_iteration: i32,
+    to_iteration: i32,
+) -> Result<(String, String), PrDocumentError> {
+    let missing = |iteration: i32, commit: &'static str| PrDocumentError::MissingCommit {
+        pr_number: document.id,
+        iteration,
+        commit,
+    };
+
+    let to = document.iteration(to_iteration)?;
+    let to_commit = to.source_commit.ok_or_else(|| missing(to_iteration, "source"))?;
+
+    let from_commit = if from_iteration == 0 {
+        to.common_ref_commit.ok_or_else(|| missing(to_iteration, "common ref"))?
+    } else {
+        document.iteration(from_iteration)?
+            .source_commit
+            .ok_or_else(|| missing(from_iteration, "source"))?
+    };
+
+    Ok((from_commit, to_commit))
+}
+
+// Helper function to resolve the commits to diff for a whole PR: its latest source commit against
+// the common ref commit recorded for that iteration, or else against the merge base with the target
+fn pr_diff_commits(repo: &Repository, document: &PrDocument) -> Result<(String, String, bool), String> {
+    let latest = document.iterations().pop();
+
+    let source_commit = document.last_merge_source_commit.clone()
+        .or_else(|| latest.as_ref()?.source_commit.clone())
+        .ok_or_else(|| format!("PR #{} has no source commit", document.id))?;
+
+    // The recorded common ref commit only applies to the iteration it was recorded for
+    let common_ref_commit = latest.as_ref()
+        .filter(|iteration| iteration.source_commit.as_ref() == Some(&source_commit))
+        .and_then(|iteration| iteration.common_ref_commit.clone());
+    if let Some(base_commit) = common_ref_commit {
+        return Ok((base_commit, source_commit, true));
     }
+
+    let target_commit = document.last_merge_target_commit.clone()
+        .or_else(|| latest.as_ref()?.target_commit.clone())
+        .ok_or_else(|| format!("PR #{} has no target commit", document.id))?;
+    let base_commit = get_merge_base(repo, &source_commit, &target_commit)
+        .map_err(|err| err.to_string())?;
+
+    Ok((base_commit, source_commit, false))
+}
+
+#[tauri::command(async)]
+fn get_pr(pr_number: i32, state: State<AppState>) -> Result<PrDocument, String> {
+    let start = Instant::now();
+
+    let document = read_pr_document(&state, pr_number)
+        .map_err(|err| err.to_string())?;
+
+    println!("Performance: get_pr parsed PR #{} with {} threads in {:?}",
+             pr_number, document.threads.len(), start.elapsed());
+
+    Ok(document)
+}
+
+#[tauri::command(async)]
+fn get_pr_iterations(pr_number: i32, state: State<AppState>) -> Result<Vec<IterationDetails>, String> {
+    read_pr_document(&state, pr_number)
+        .map(|document| document.iterations())
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn git_get_pr_iteration_diff(
+    pr_number: i32,
+    from_iteration: i32,
+    to_iteration: i32,
+    state: State<AppState>
+) -> Result<TreeDiff, String> {
+    let start = Instant::now();
+
+    let document = read_pr_document(&state, pr_number)
+        .map_err(|err| err.to_string())?;
+    let (from_commit, to_commit) = iteration_diff_commits(&document, from_iteration, to_iteration)
+        .map_err(|err| err.to_string())?;
+
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    let result = get_tree_diff_between_revisions(
+        &r,
+        &from_commit,
+        &to_commit,
+        &settings,
+    )
+        .map_err(|err| err.to_string());
+
+    println!("Performance: git_get_pr_iteration_diff diffed PR #{} iterations {}..{} in {:?}",
+             pr_number, from_iteration, to_iteration, start.elapsed());
+
+    result
+}
+
+#[tauri::command(async)]
+fn git_get_pr_diff(
+    pr_number: i32,
+    state: State<AppState>
+) -> Result<PrDiff, String> {
+    let start = Instant::now();
+
+    let document = read_pr_document(&state, pr_number)
+        .map_err(|err| err.to_string())?;
+
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    let (base_commit, source_commit, base_from_archive) = pr_diff_commits(&r, &document)?;
+    let diff = get_tree_diff_between_revisions(
+        &r,
+        &base_commit,
+        &source_commit,
+        &settings,
+    )
+        .map_err(|err| err.to_string())?;
+
+    println!("Performance: git_get_pr_diff diffed PR #{} in {:?}", pr_number, start.elapsed());
+
+    Ok(PrDiff { base_commit, source_commit, base_from_archive, diff })
+}
+
+#[tauri::command(async)]
+fn get_pr_commits(
+    pr_number: i32,
+    line_stats: Option<bool>,
+    state: State<AppState>
+) -> Result<Vec<CommitMetadata>, String> {
+    let start = Instant::now();
+
+    let document = read_pr_document(&state, pr_number)
+        .map_err(|err| err.to_string())?;
+
+    let r = state.repos.get()?;
+
+    let (base_commit, source_commit, _) = pr_diff_commits(&r, &document)?;
+    let result = get_pr_commit_series(&r, &source_commit, &base_commit, line_stats.unwrap_or(false))
+        .map_err(|err| err.to_string());
+
+    println!("Performance: get_pr_commits listed the commits of PR #{} in {:?}", pr_number, start.elapsed());
+
+    result
 }
 
 #[tauri::command(async)]
@@ -186,30 +582,24 @@ fn list_files(state: State<AppState>) -> Result<Vec<String>, String> {
 }
 
 #[tauri::command(async)]
-async fn set_git_repo(
+fn set_git_repo(
     file_path: String,
-    state: State<'_, AppState>
+    state: State<AppState>
 ) -> Result<String, String> {
-    let repo = Repository::open(file_path);
-    match repo {
-        Ok(r) => {
-            // Lock the mutex and update the option
-            let mut repo_guard = state.repo.lock().await;
-            *repo_guard = Some(r);
-            Ok("OK".to_string())
-        },
+    match state.repos.set_repository(Path::new(&file_path)) {
+        Ok(()) => Ok("OK".to_string()),
         Err(err) => Err(err.to_string()),
     }
 }
 
 #[tauri::command(async)]
-async fn get_git_repo(
-    state: State<'_, AppState>
+fn get_git_repo(
+    state: State<AppState>
 ) -> Result<String, String> {
-    let repo_guard = state.repo.lock().await;
-    Ok(match *repo_guard {
-        Some(ref repo) => {
-            repo.path().to_str()
+    let repo_path = state.repos.path();
+    Ok(match repo_path {
+        Some(ref path) => {
+            path.to_str()
                 .map(|s|
                     if let Some(index) = s.find("/.git") {
                         &s[0..index]
@@ -223,108 +613,344 @@ async fn get_git_repo(
     }.to_owned())
 }
 
-#[tauri::command]
-async fn get_git_commit(
+#[tauri::command(async)]
+fn get_git_commit(
     revision: String,
-    state: State<'_, AppState>
+    state: State<AppState>
 ) -> Result<CommitMetadata, String> {
-    let repo_lock = state.repo.lock().await;
-    match &*repo_lock {
-        Some(r) => {
-            get_commit_metadata(r, &revision)
-                .map_err(|err| err.to_string())
-        },
-        None => Err("No repository selected".to_string()),
-    }
+    let r = state.repos.get()?;
+
+    get_commit_metadata(&r, &revision)
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command(async)]
-async fn get_git_file_lines_at_revision(
+fn get_git_file_lines_at_revision(
     file_path: String,
     revision: String,
     start_line: usize,
     end_line: usize,
-    state: State<'_, AppState>
-) -> Result<Vec<String>, String> {
-    let repo_lock = state.repo.lock().await;
-
-    match &*repo_lock {
-        Some(r) => {
-            get_file_lines_at_revision(r, &file_path, &revision, std::ops::Range { start: start_line, end: end_line })
-                .map_err(|err| err.to_string())
-        },
-        None => Err("No repository selected".to_string()),
-    }
+    highlight: Option<bool>,
+    state: State<AppState>
+) -> Result<FileLines, String> {
+    let r = state.repos.get()?;
+
+    get_file_lines_at_revision(
+        &r,
+        &state.git_cache,
+        &file_path,
+        &revision,
+        std::ops::Range { start: start_line, end: end_line },
+        highlight.unwrap_or(false),
+    )
+        .map_err(|err| err.to_string())
 }
 
 #[tauri::command(async)]
-async fn git_get_file_diff_between_revisions(
+fn git_get_file_diff_between_revisions(
     file_path: String,
     from_revision: String,
     to_revision: String,
     start_line: usize,
     end_line: usize,
-    state: State<'_, AppState>
-) -> Result<String, String> {
-    let repo_lock = state.repo.lock().await;
-
-    match &*repo_lock {
-        Some(r) => {
-            get_file_diff_as_strings(
-                r,
-                &file_path,
-                &from_revision,
-                &to_revision,
-                std::ops::Range { start: start_line, end: end_line }
-            )
-                .map_err(|err| err.to_string())
-        },
-        None => Err("No repository selected".to_string()),
-    }
+    state: State<AppState>
+) -> Result<Vec<DiffLineInfo>, String> {
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    get_file_diff_between_revisions(
+        &r,
+        &state.git_cache,
+        &file_path,
+        &from_revision,
+        &to_revision,
+        std::ops::Range { start: start_line, end: end_line },
+        settings.context_lines,
+    )
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn git_get_file_diffs_between_revisions(
+    requests: Vec<FileDiffRequest>,
+    state: State<AppState>
+) -> Result<Vec<FileDiffResult>, String> {
+    let start = Instant::now();
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    // Requests on the same file and revisions are served from a single diff, through the cache
+    let results: Vec<FileDiffResult> = requests.iter()
+        .map(|request| {
+            let result = get_file_diff_between_revisions(
+                &r,
+                &state.git_cache,
+                &request.file_path,
+                &request.from_revision,
+                &request.to_revision,
+                std::ops::Range { start: request.start_line, end: request.end_line },
+                settings.context_lines,
+            );
+            match result {
+                Ok(diff) => FileDiffResult { diff: Some(diff), error: None },
+                Err(err) => FileDiffResult { diff: None, error: Some(err.to_string()) },
+            }
+        })
+        .collect();
+
+    println!("Performance: git_get_file_diffs_between_revisions diffed {} ranges in {:?}",
+             requests.len(), start.elapsed());
+
+    Ok(results)
+}
+
+#[tauri::command(async)]
+fn get_thread_snippets(
+    pr_number: i32,
+    state: State<AppState>
+) -> Result<Vec<ThreadSnippet>, String> {
+    let start = Instant::now();
+
+    let document = read_pr_document(&state, pr_number)
+        .map_err(|err| err.to_string())?;
+
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    let snippets = thread_snippets::get_thread_snippets(&r, &state.git_cache, &document, settings.context_lines);
+
+    println!("Performance: get_thread_snippets read {} snippets of PR #{} in {:?}",
+             snippets.len(), pr_number, start.elapsed());
+
+    Ok(snippets)
+}
+
+#[tauri::command(async)]
+fn git_blame_range(
+    file_path: String,
+    revision: String,
+    start_line: usize,
+    end_line: usize,
+    state: State<AppState>
+) -> Result<Vec<BlameLine>, String> {
+    let start = Instant::now();
+
+    // PR lookup is best effort, blame still works without an archive
+    let commit_index = match ensure_commit_index(&state) {
+        Ok(()) => Some(&state.commits),
+        Err(e) => {
+            println!("Blaming without PR lookup: {}", e);
+            None
+        }
+    };
+
+    let r = state.repos.get()?;
+
+    let result = blame_range(
+        &r,
+        &file_path,
+        &revision,
+        std::ops::Range { start: start_line, end: end_line },
+        commit_index,
+    )
+        .map_err(|err| err.to_string());
+
+    println!("Performance: git_blame_range blamed {}:{}-{} at {} in {:?}",
+             file_path, start_line, end_line, revision, start.elapsed());
+
+    result
 }
 
 #[tauri::command(async)]
-async fn git_get_tree_diff_between_revisions(
+fn find_pr_for_commit(
+    revision: String,
+    state: State<AppState>
+) -> Result<Option<PrCommitMatch>, String> {
+    let start = Instant::now();
+
+    ensure_commit_index(&state)?;
+    let r = state.repos.get()?;
+
+    let commit_id = resolve_commit_id(&r, &revision)?;
+    let result = state.commits.find_pr_for_commit(&r, commit_id)
+        .map_err(|err| err.to_string());
+
+    println!("Performance: find_pr_for_commit looked up {} in {:?}", revision, start.elapsed());
+
+    result
+}
+
+#[tauri::command(async)]
+fn scan_missing_commits(state: State<AppState>) -> Result<MissingCommitsReport, String> {
+    let refs = load_pr_commit_refs(&state.fs)?;
+    let r = state.repos.get()?;
+
+    missing_commits::scan_missing_commits(&r, &refs)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn fetch_missing_commits(
+    remote: String,
+    commit_ids: Option<Vec<String>>,
+    app: AppHandle,
+    state: State<AppState>
+) -> Result<FetchReport, String> {
+    // Fetch through a handle of the repository outside of the pool, so that the fetch doesn't
+    // take one of the handles of the other commands for its whole duration
+    let repo_path = state.repos.path().ok_or("No repository selected")?;
+    let repo = Repository::open(repo_path).map_err(|err| err.to_string())?;
+
+    // Without explicit commits, fetch all the commits of the archive that are missing
+    let commit_ids = match commit_ids {
+        Some(commit_ids) => commit_ids,
+        None => missing_commits::scan_missing_commits(&repo, &load_pr_commit_refs(&state.fs)?)
+            .map_err(|err| err.to_string())?
+            .missing
+            .into_iter()
+            .map(|commit| commit.commit_id)
+            .collect(),
+    };
+
+    missing_commits::fetch_commits(&repo, &remote, &commit_ids, &mut |progress: &FetchProgress| {
+        if let Err(e) = app.emit("missing-commits-fetch-progress", progress) {
+            println!("Failed to report fetch progress: {}", e);
+        }
+    })
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn get_diff_settings(state: State<AppState>) -> DiffSettings {
+    *state.diff_settings.read().unwrap()
+}
+
+#[tauri::command(async)]
+fn set_diff_settings(settings: DiffSettings, state: State<AppState>) {
+    *state.diff_settings.write().unwrap() = settings;
+}
+
+#[tauri::command(async)]
+fn git_get_tree_diff_between_revisions(
     from_revision: String,
     to_revision: String,
-    state: State<'_, AppState>
+    state: State<AppState>
 ) -> Result<TreeDiff, String> {
-    let repo_lock = state.repo.lock().await;
-
-    match &*repo_lock {
-        Some(r) => {
-            get_tree_diff_between_revisions(
-                r,
-                &from_revision,
-                &to_revision
-            )
-                .map_err(|err| err.to_string())
-        },
-        None => Err("No repository selected".to_string()),
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    get_tree_diff_between_revisions(
+        &r,
+        &from_revision,
+        &to_revision,
+        &settings,
+    )
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn git_get_tree_diff_summary(
+    from_revision: String,
+    to_revision: String,
+    file_pattern: Option<String>,
+    state: State<AppState>
+) -> Result<TreeDiffSummary, String> {
+    let start = Instant::now();
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    let result = get_tree_diff_summary(
+        &r,
+        &from_revision,
+        &to_revision,
+        file_pattern.as_deref(),
+        &settings,
+    )
+        .map_err(|err| err.to_string());
+
+    println!("Performance: git_get_tree_diff_summary completed in {:?}", start.elapsed());
+
+    result
+}
+
+#[tauri::command(async)]
+fn git_get_file_diff(
+    from_revision: String,
+    to_revision: String,
+    file_path: String,
+    highlight: Option<bool>,
+    state: State<AppState>
+) -> Result<FileDiff, String> {
+    let start = Instant::now();
+    let mut settings = *state.diff_settings.read().unwrap();
+    // A single file is cheap enough to highlight, unlike whole trees
+    if let Some(highlight) = highlight {
+        settings.syntax_highlight = highlight;
     }
+    let r = state.repos.get()?;
+
+    let result = get_file_diff(
+        &r,
+        &from_revision,
+        &to_revision,
+        &file_path,
+        &settings,
+    )
+        .map_err(|err| err.to_string());
+
+    println!("Performance: git_get_file_diff for {} completed in {:?}", file_path, start.elapsed());
+
+    result
 }
 
 #[tauri::command(async)]
-async fn git_get_filtered_tree_diff(
+fn git_get_context_lines(
+    file_path: String,
+    from_revision: String,
+    to_revision: String,
+    hunk: HunkRange,
+    direction: ContextDirection,
+    count: u32,
+    highlight: Option<bool>,
+    state: State<AppState>
+) -> Result<Vec<LineDiff>, String> {
+    let mut settings = *state.diff_settings.read().unwrap();
+    if let Some(highlight) = highlight {
+        settings.syntax_highlight = highlight;
+    }
+    let r = state.repos.get()?;
+
+    get_context_lines(
+        &r,
+        &file_path,
+        &from_revision,
+        &to_revision,
+        &hunk,
+        direction,
+        count,
+        &settings,
+    )
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn git_get_filtered_tree_diff(
     from_revision: String,
     to_revision: String,
     file_pattern: String,
-    state: State<'_, AppState>
+    state: State<AppState>
 ) -> Result<TreeDiff, String> {
-    let repo_lock = state.repo.lock().await;
-
-    match &*repo_lock {
-        Some(r) => {
-            get_filtered_tree_diff(
-                r,
-                &from_revision,
-                &to_revision,
-                &file_pattern,
-            )
-                .map_err(|err| err.to_string())
-        },
-        None => Err("No repository selected".to_string()),
-    }
+    let settings = *state.diff_settings.read().unwrap();
+    let r = state.repos.get()?;
+
+    get_filtered_tree_diff(
+        &r,
+        &from_revision,
+        &to_revision,
+        &file_pattern,
+        &settings,
+    )
+        .map_err(|err| err.to_string())
 }
 
 // Define the InitialState struct
@@ -376,17 +1002,53 @@ fn initialize_git_repo(repo_path: &Option<String>) -> Result<Option<Repository>,
     }
 }
 
+/// Find the PR of the archive that introduced a commit of the repository, without starting
+/// the application (`--which-pr`)
+pub fn which_pr(archive_path: &str, repo_path: &str, revision: &str) -> Result<Option<PrCommitMatch>, String> {
+    let fs = initialize_filesystem(&Some(archive_path.to_string()), "PR")?;
+    let repo = initialize_git_repo(&Some(repo_path.to_string()))?
+        .ok_or("No repository selected")?;
+
+    let commits = CommitIndex::new();
+    commits.rebuild_index(load_pr_commit_refs(&fs)?);
+
+    let commit_id = resolve_commit_id(&repo, revision)?;
+    commits.find_pr_for_commit(&repo, commit_id)
+        .map_err(|err| err.to_string())
+}
+
+/// Check which commits referenced by an archive are missing from a repository, without starting
+/// the application (`--scan-missing-commits`)
+pub fn find_missing_commits(archive_path: &str, repo_path: &str) -> Result<MissingCommitsReport, String> {
+    let fs = initialize_filesystem(&Some(archive_path.to_string()), "PR")?;
+    let repo = initialize_git_repo(&Some(repo_path.to_string()))?
+        .ok_or("No repository selected")?;
+
+    missing_commits::scan_missing_commits(&repo, &load_pr_commit_refs(&fs)?)
+        .map_err(|err| err.to_string())
+}
+
 // Helper function to create the AppState from initialized components
 fn create_app_state(
     fs: FileSystem,
     images_fs: FileSystem,
     repo_option: Option<Repository>
 ) -> AppState {
+    // One handle per core, as reads beyond that only compete for the CPU
+    let max_handles = thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+    let repos = RepoPool::new(max_handles);
+    if let Some(repo) = repo_option {
+        repos.set_opened_repository(repo);
+    }
+
     AppState {
         fs,                              // Main PR data archive
         images_fs,                       // Separate images archive
         search: SearchIndex::new(),
-        repo: Arc::new(Mutex::new(repo_option)),
+        commits: CommitIndex::new(),
+        diff_settings: RwLock::new(DiffSettings::default()),
+        repos,
+        git_cache: GitCache::default(),
     }
 }
 
@@ -531,14 +1193,32 @@ pub fn run(initial_state: Option<InitialState>) -> Result<(), String> {
             get_pr_files,
             set_archive_file,
             read_pr_file,
+            get_pr,
             search_prs,
+            get_search_tokenizer_options,
+            set_search_tokenizer_options,
             list_files,
             set_git_repo,
             get_git_commit,
             get_git_file_lines_at_revision,
             git_get_file_diff_between_revisions,
+            git_get_file_diffs_between_revisions,
+            get_thread_snippets,
+            git_blame_range,
+            find_pr_for_commit,
+            scan_missing_commits,
+            fetch_missing_commits,
             git_get_tree_diff_between_revisions,
+            get_diff_settings,
+            set_diff_settings,
+            get_pr_iterations,
+            git_get_pr_iteration_diff,
+            git_get_pr_diff,
+            get_pr_commits,
             git_get_filtered_tree_diff,
+            git_get_tree_diff_summary,
+            git_get_file_diff,
+            git_get_context_lines,
             get_git_repo,
             get_archive_path,
             get_images_archive_path,
@@ -1,10 +1,10 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::process;
+use std::{fs, process};
 
 use clap::Arg;
-use azure_pr_viewer_lib::{run, InitialState};
+use azure_pr_viewer_lib::{find_missing_commits, run, which_pr, InitialState};
 
 fn main() {
     // Parse command line arguments
@@ -27,8 +27,74 @@ fn main() {
             .long("repo")
             .value_name("PATH")
             .help("Sets the git repository path"))
+        .arg(Arg::new("which-pr")
+            .long("which-pr")
+            .value_name("SHA")
+            .requires_all(["archive", "repo"])
+            .help("Prints the PR that introduced a commit and exits"))
+        .arg(Arg::new("scan-missing-commits")
+            .long("scan-missing-commits")
+            .action(clap::ArgAction::SetTrue)
+            .requires_all(["archive", "repo"])
+            .help("Prints the commits referenced by the archive that are missing from the repository and exits"))
+        .arg(Arg::new("fetch-script")
+            .long("fetch-script")
+            .value_name("FILE")
+            .requires("scan-missing-commits")
+            .help("Writes a script fetching the missing commits in batches"))
         .get_matches();
 
+    // Answer the commit lookup without starting the application
+    if let Some(revision) = matches.get_one::<String>("which-pr") {
+        let archive_path = matches.get_one::<String>("archive").unwrap();
+        let repo_path = matches.get_one::<String>("repo").unwrap();
+
+        match which_pr(archive_path, repo_path, revision) {
+            Ok(Some(pr_match)) if pr_match.via_ancestry => println!(
+                "{}: PR #{} (merged through {})",
+                revision, pr_match.pr_number, pr_match.matched_commit
+            ),
+            Ok(Some(pr_match)) => println!("{}: PR #{}", revision, pr_match.pr_number),
+            Ok(None) => {
+                println!("{}: no PR found", revision);
+                process::exit(2);
+            },
+            Err(e) => {
+                eprintln!("Failed to look up {}: {}", revision, e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // List the missing commits, one per line, without starting the application
+    if matches.get_flag("scan-missing-commits") {
+        let archive_path = matches.get_one::<String>("archive").unwrap();
+        let repo_path = matches.get_one::<String>("repo").unwrap();
+
+        let report = match find_missing_commits(archive_path, repo_path) {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("Failed to scan for missing commits: {}", e);
+                process::exit(1);
+            }
+        };
+
+        for commit in &report.missing {
+            println!("{}", commit.commit_id);
+        }
+        eprintln!("{} of {} referenced commits are missing", report.missing.len(), report.referenced);
+
+        if let Some(script_path) = matches.get_one::<String>("fetch-script") {
+            if let Err(e) = fs::write(script_path, &report.fetch_script) {
+                eprintln!("Failed to write {}: {}", script_path, e);
+                process::exit(1);
+            }
+            eprintln!("Wrote the fetch script to {}", script_path);
+        }
+        return;
+    }
+
     // Create the InitialState based on CLI arguments
     let initial_state = InitialState {
         archive_path: matches.get_one::<String>("archive").cloned(),
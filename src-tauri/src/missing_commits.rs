@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use git2::{Cred, CredentialType, FetchOptions, Oid, RemoteCallbacks, Repository};
+use serde::Serialize;
+
+use crate::commit_index::{parse_commit_id, PrCommitRefs};
+
+/// Commits fetched per `git fetch` of the fetch script. Remotes reject requests listing too many
+/// commits, and 100 has proven to work well against Azure DevOps.
+pub const FETCH_BATCH_SIZE: usize = 100;
+
+// Fetched commits are kept reachable under this namespace, so that `git gc` doesn't prune them
+const FETCHED_REFS_PREFIX: &str = "refs/pr-viewer/commits/";
+
+// Minimum interval between two transfer progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A commit referenced by the archive that the repository doesn't have
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingCommit {
+    pub commit_id: String,
+    /// The PRs referencing the commit
+    pub pr_numbers: Vec<i32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingCommitsReport {
+    /// Number of distinct commits referenced by the archive
+    pub referenced: usize,
+    pub missing: Vec<MissingCommit>,
+    /// A shell script fetching the missing commits in batches, to run from the repository
+    pub fetch_script: String,
+}
+
+/// Check which of the commits referenced by the PRs of the archive are missing from the
+/// repository, typically the commits of PRs whose branches were deleted on the remote before the
+/// repository was cloned.
+pub fn scan_missing_commits(repo: &Repository, refs: &[PrCommitRefs]) -> Result<MissingCommitsReport, git2::Error> {
+    let start = Instant::now();
+
+    // Commit id -> PRs referencing it, sorted so that the report is stable
+    let mut referenced: BTreeMap<Oid, Vec<i32>> = BTreeMap::new();
+    for pr in refs {
+        for commit in pr.referenced_commits() {
+            let Some(oid) = parse_commit_id(commit) else {
+                println!("Skipping invalid commit id '{}' of PR #{}", commit, pr.id);
+                continue;
+            };
+
+            let pr_numbers = referenced.entry(oid).or_default();
+            if !pr_numbers.contains(&pr.id) {
+                pr_numbers.push(pr.id);
+            }
+        }
+    }
+
+    // Looking objects up in the odb directly is much faster than resolving them as revisions
+    let odb = repo.odb()?;
+    let missing: Vec<MissingCommit> = referenced.iter()
+        .filter(|(oid, _)| !odb.exists(**oid))
+        .map(|(oid, pr_numbers)| MissingCommit {
+            commit_id: oid.to_string(),
+            pr_numbers: pr_numbers.clone(),
+        })
+        .collect();
+
+    println!("Performance: found {} of {} referenced commits missing in {:?}",
+             missing.len(), referenced.len(), start.elapsed());
+
+    Ok(MissingCommitsReport {
+        referenced: referenced.len(),
+        fetch_script: fetch_script(&missing),
+        missing,
+    })
+}
+
+/// Build a shell script fetching commits by id in batches. Commits that are no longer reachable
+/// from any branch can still be fetched by id, but not through any ref.
+pub fn fetch_script(missing: &[MissingCommit]) -> String {
+    let mut script = String::from("#!/bin/sh\n");
+    script.push_str(&format!(
+        "# Fetches the {} commits referenced by the PR archive that are missing from this repository.\n",
+        missing.len()
+    ));
+    script.push_str("# Run from the repository, optionally passing the remote to fetch from (default: origin).\n");
+    script.push_str("remote=\"${1:-origin}\"\n");
+    // A commit unknown to the remote fails its whole batch, so the other batches still run
+    script.push_str("failed=0\n");
+
+    let batch_count = missing.len().div_ceil(FETCH_BATCH_SIZE);
+    for (index, batch) in missing.chunks(FETCH_BATCH_SIZE).enumerate() {
+        script.push_str(&format!("\necho \"Fetching batch {} of {}\"\n", index + 1, batch_count));
+        script.push_str("git fetch --no-tags --no-write-fetch-head \"$remote\"");
+        for commit in batch {
+            script.push_str(" \\\n    ");
+            script.push_str(&commit.commit_id);
+        }
+        script.push_str(&format!(" \\\n    || {{ echo \"Batch {} failed\" >&2; failed=1; }}\n", index + 1));
+    }
+
+    script.push_str("\nexit $failed\n");
+    script
+}
+
+/// Progress of a fetch of missing commits, reported at each batch and while objects are received
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchProgress {
+    /// The batch being fetched (1-based)
+    pub batch: usize,
+    pub batch_count: usize,
+    /// Commits fetched so far, out of all requested
+    pub fetched_commits: usize,
+    pub total_commits: usize,
+    /// Objects received for the current batch
+    pub received_objects: usize,
+    pub total_objects: usize,
+    pub received_bytes: usize,
+}
+
+/// A commit that couldn't be fetched, typically because the remote doesn't have it either
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedFetch {
+    pub commit_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchReport {
+    pub fetched: Vec<String>,
+    pub failed: Vec<FailedFetch>,
+}
+
+/// Fetch commits by id in batches, from a remote of the repository or from a URL or path (e.g. a
+/// local bare mirror). A batch that fails as a whole is retried commit by commit, so that commits
+/// the remote doesn't have only fail themselves. Fetched commits are kept under
+/// `refs/pr-viewer/commits/`. Ids that aren't full commit hashes fail without being fetched.
+///
+/// Remotes must allow fetching commits by id, as Azure DevOps does. Local paths only provide the
+/// commits reachable from their refs (e.g. `refs/pull/*` in a mirror of Azure DevOps).
+///
+/// # Arguments
+///
+/// * `repo` - The Git repository to fetch into
+/// * `remote` - Name of a remote of the repository, or a URL or path to fetch from
+/// * `commit_ids` - The commits to fetch
+/// * `on_progress` - Called with the progress of the fetch
+///
+pub fn fetch_commits(
+    repo: &Repository,
+    remote: &str,
+    commit_ids: &[String],
+    on_progress: &mut dyn FnMut(&FetchProgress),
+) -> Result<FetchReport, git2::Error> {
+    let start = Instant::now();
+
+    let mut remote = match repo.find_remote(remote) {
+        Ok(remote) => remote,
+        Err(_) => repo.remote_anonymous(remote)?,
+    };
+
+    let mut report = FetchReport { fetched: Vec::new(), failed: Vec::new() };
+
+    // The ids end up in refspecs, so anything but a full hash is rejected before fetching
+    let mut commits = Vec::new();
+    for commit_id in commit_ids {
+        match parse_commit_id(commit_id) {
+            Some(oid) => commits.push(oid),
+            None => report.failed.push(FailedFetch {
+                commit_id: commit_id.clone(),
+                error: format!("Invalid commit id: {}", commit_id),
+            }),
+        }
+    }
+
+    let mut progress = FetchProgress {
+        batch: 0,
+        batch_count: commits.len().div_ceil(FETCH_BATCH_SIZE),
+        fetched_commits: 0,
+        total_commits: commits.len(),
+        received_objects: 0,
+        total_objects: 0,
+        received_bytes: 0,
+    };
+
+    for batch in commits.chunks(FETCH_BATCH_SIZE) {
+        progress.batch += 1;
+        progress.received_objects = 0;
+        progress.total_objects = 0;
+        progress.received_bytes = 0;
+        on_progress(&progress);
+
+        let results = match fetch_batch(&mut remote, batch, &mut progress, on_progress) {
+            Ok(()) => batch.iter().map(|commit_id| (commit_id, Ok(()))).collect(),
+            Err(_) if batch.len() > 1 => batch.iter()
+                .map(|commit| {
+                    let result = fetch_batch(&mut remote, std::slice::from_ref(commit), &mut progress, on_progress);
+                    (commit, result)
+                })
+                .collect(),
+            Err(e) => vec![(&batch[0], Err(e))],
+        };
+
+        for (commit, result) in results {
+            match result {
+                Ok(()) => report.fetched.push(commit.to_string()),
+                Err(e) => report.failed.push(FailedFetch { commit_id: commit.to_string(), error: e.message().to_string() }),
+            }
+        }
+
+        progress.fetched_commits = report.fetched.len();
+        on_progress(&progress);
+    }
+
+    println!("Performance: fetched {} of {} commits in {:?}",
+             report.fetched.len(), commit_ids.len(), start.elapsed());
+
+    Ok(report)
+}
+
+// Fetch a batch of commits, each into its own ref under the fetched commits namespace
+fn fetch_batch(
+    remote: &mut git2::Remote,
+    commits: &[Oid],
+    progress: &mut FetchProgress,
+    on_progress: &mut dyn FnMut(&FetchProgress),
+) -> Result<(), git2::Error> {
+    let refspecs: Vec<String> = commits.iter()
+        .map(|commit| format!("{}:{}{}", commit, FETCHED_REFS_PREFIX, commit))
+        .collect();
+
+    let mut last_report = Instant::now();
+    let mut callbacks = RemoteCallbacks::new();
+    // libgit2 asks again after each rejected credential, so each kind is only tried once
+    let mut tried = CredentialType::empty();
+    callbacks.credentials(|url, username, allowed| {
+        // Use the same credentials as git: the credential helpers, or the SSH agent
+        let untried = allowed - tried;
+        if untried.contains(CredentialType::USERNAME) {
+            tried |= CredentialType::USERNAME;
+            return Cred::username(username.unwrap_or("git"));
+        }
+        if untried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if untried.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username);
+        }
+        Err(git2::Error::from_str(&format!("Authentication to {} failed", url)))
+    });
+    callbacks.transfer_progress(|stats| {
+        progress.received_objects = stats.received_objects();
+        progress.total_objects = stats.total_objects();
+        progress.received_bytes = stats.received_bytes();
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(progress);
+            last_report = Instant::now();
+        }
+        true
+    });
+
+    let mut fetch_opts = FetchOptions::new();
+    fetch_opts
+        .remote_callbacks(callbacks)
+        .download_tags(git2::AutotagOption::None);
+
+    remote.fetch(&refspecs, Some(&mut fetch_opts), None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::Signature;
+    use tempfile::TempDir;
+
+    // A repository with a single empty commit
+    fn repo_with_commit() -> (TempDir, Repository, Oid) {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let signature = Signature::now("Test", "test@example.com").unwrap();
+        let tree = repo.find_tree(repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let commit = repo.commit(Some("HEAD"), &signature, &signature, "Initial", &tree, &[]).unwrap();
+        drop(tree);
+
+        (dir, repo, commit)
+    }
+
+    fn pr(id: i32, merge: &str, source: &str) -> PrCommitRefs {
+        PrCommitRefs {
+            id,
+            last_merge_source_commit: Some(source.to_string()),
+            last_merge_target_commit: None,
+            last_merge_commit: Some(merge.to_string()),
+            threads: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reports_only_full_commit_ids_missing_from_the_repository() {
+        let (_dir, repo, present) = repo_with_commit();
+        let present = present.to_string();
+        let missing = "0123456789abcdef0123456789abcdef01234567";
+
+        let refs = [
+            pr(1, &present, missing),
+            pr(2, missing, "not-a-commit"),
+            // A prefix of the present commit, which `Oid::from_str` would zero-pad into a
+            // commit that doesn't exist
+            pr(3, &present[..7], &present[..7].to_uppercase()),
+        ];
+        let report = scan_missing_commits(&repo, &refs).unwrap();
+
+        assert_eq!(report.referenced, 2);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].commit_id, missing);
+        assert_eq!(report.missing[0].pr_numbers, vec![1, 2]);
+        assert!(report.fetch_script.contains(missing));
+        assert!(!report.fetch_script.contains(&present));
+    }
+
+    #[test]
+    fn reports_nothing_missing_when_every_commit_is_present() {
+        let (_dir, repo, present) = repo_with_commit();
+        let present = present.to_string();
+
+        let report = scan_missing_commits(&repo, &[pr(1, &present, &present)]).unwrap();
+
+        assert_eq!(report.referenced, 1);
+        assert!(report.missing.is_empty());
+    }
+}
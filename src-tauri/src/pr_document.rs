@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::PrReviewer;
+
+/// A full PR document (`prs/pr_<id>.json`) as written by `azure-prs-process.py`
+///
+/// Top-level fields are snake_case (chosen by the exporter), everything nested below
+/// them keeps the camelCase names of the Azure DevOps REST API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrDocument {
+    pub id: i32,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub created_by: String,
+    #[serde(default)]
+    pub created_by_id: Option<String>,
+    #[serde(default)]
+    pub reviewers: Vec<PrReviewer>,
+    pub creation_date: DateTime<Utc>,
+    #[serde(default)]
+    pub completion_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub auto_complete_set_by: Option<String>,
+    pub repository: String,
+    #[serde(default)]
+    pub repository_id: Option<String>,
+    pub source_branch: String,
+    pub target_branch: String,
+    #[serde(default)]
+    pub merge_status: Option<String>,
+    #[serde(default)]
+    pub merge_id: Option<String>,
+    #[serde(default)]
+    pub last_merge_source_commit: Option<String>,
+    #[serde(default)]
+    pub last_merge_target_commit: Option<String>,
+    #[serde(default)]
+    pub last_merge_commit: Option<String>,
+    pub status: String,
+    #[serde(default)]
+    pub is_draft: bool,
+    #[serde(default)]
+    pub has_conflicts: bool,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default, rename = "supportsIterations")]
+    pub supports_iterations: bool,
+    #[serde(default)]
+    pub work_item_refs: Vec<WorkItemRef>,
+    #[serde(default)]
+    pub completion_options: Option<CompletionOptions>,
+    #[serde(default)]
+    pub completion_queue_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub threads: Vec<Thread>,
+}
+
+impl PrDocument {
+    /// The PR's iterations, ordered by id
+    ///
+    /// The exporter only records iterations as part of thread contexts, so iterations that no
+    /// file thread compared against are missing.
+    pub fn iterations(&self) -> Vec<IterationDetails> {
+        let mut iterations: BTreeMap<i32, IterationDetails> = BTreeMap::new();
+
+        let contexts = self.threads.iter()
+            .filter_map(|thread| thread.pull_request_thread_context.as_ref());
+        for context in contexts {
+            let details = [&context.first_iteration_details, &context.second_iteration_details];
+            for iteration in details.into_iter().flatten() {
+                iterations.entry(iteration.id).or_insert_with(|| iteration.clone());
+            }
+        }
+
+        iterations.into_values().collect()
+    }
+
+    /// Look up a single iteration by id
+    pub fn iteration(&self, id: i32) -> Result<IterationDetails, PrDocumentError> {
+        self.iterations().into_iter()
+            .find(|iteration| iteration.id == id)
+            .ok_or(PrDocumentError::IterationNotFound { pr_number: self.id, iteration: id })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkItemRef {
+    pub id: String,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    #[serde(default)]
+    pub merge_commit_message: Option<String>,
+    #[serde(default)]
+    pub squash_merge: Option<bool>,
+    #[serde(default)]
+    pub merge_strategy: Option<String>,
+    #[serde(default)]
+    pub delete_source_branch: Option<bool>,
+    #[serde(default)]
+    pub transition_work_items: Option<bool>,
+    #[serde(default)]
+    pub bypass_policy: Option<bool>,
+    #[serde(default)]
+    pub bypass_reason: Option<String>,
+    #[serde(default)]
+    pub triggered_by_auto_complete: Option<bool>,
+}
+
+/// A `{ "href": ... }` link object, as used in `_links`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Href {
+    pub href: String,
+}
+
+pub type Links = BTreeMap<String, Href>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identity {
+    pub id: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub unique_name: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub image_url: Option<String>,
+    #[serde(default)]
+    pub descriptor: Option<String>,
+    #[serde(default)]
+    pub is_container: Option<bool>,
+    #[serde(default, rename = "_links")]
+    pub links: Links,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thread {
+    pub id: i64,
+    pub published_date: DateTime<Utc>,
+    pub last_updated_date: DateTime<Utc>,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+    #[serde(default)]
+    pub thread_context: Option<ThreadContext>,
+    #[serde(default)]
+    pub pull_request_thread_context: Option<PullRequestThreadContext>,
+    #[serde(default)]
+    pub properties: ThreadProperties,
+    /// Identities referenced by system messages, keyed by their position ("1", "2", ...)
+    #[serde(default)]
+    pub identities: BTreeMap<String, Identity>,
+    #[serde(default)]
+    pub is_deleted: bool,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default, rename = "_links")]
+    pub links: Links,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub id: i64,
+    pub parent_comment_id: i64,
+    pub author: Identity,
+    #[serde(default)]
+    pub content: Option<String>,
+    pub published_date: DateTime<Utc>,
+    pub last_updated_date: DateTime<Utc>,
+    #[serde(default)]
+    pub last_content_updated_date: Option<DateTime<Utc>>,
+    /// "text", "system" or "codeChange"
+    pub comment_type: String,
+    #[serde(default)]
+    pub users_liked: Vec<Identity>,
+    #[serde(default)]
+    pub is_deleted: bool,
+    #[serde(default, rename = "_links")]
+    pub links: Links,
+}
+
+/// The file and line range a thread is attached to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadContext {
+    pub file_path: String,
+    #[serde(default)]
+    pub left_file_start: Option<FilePosition>,
+    #[serde(default)]
+    pub left_file_end: Option<FilePosition>,
+    #[serde(default)]
+    pub right_file_start: Option<FilePosition>,
+    #[serde(default)]
+    pub right_file_end: Option<FilePosition>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FilePosition {
+    pub line: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequestThreadContext {
+    #[serde(default)]
+    pub iteration_context: Option<IterationContext>,
+    #[serde(default)]
+    pub change_tracking_id: Option<i64>,
+    /// Added by the exporter from the PR's iterations
+    #[serde(default)]
+    pub first_iteration_details: Option<IterationDetails>,
+    #[serde(default)]
+    pub second_iteration_details: Option<IterationDetails>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IterationContext {
+    pub first_comparing_iteration: i32,
+    pub second_comparing_iteration: i32,
+}
+
+/// The commits of a single PR iteration (push)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IterationDetails {
+    pub id: i32,
+    #[serde(default)]
+    pub source_commit: Option<String>,
+    #[serde(default)]
+    pub target_commit: Option<String>,
+    #[serde(default)]
+    pub common_ref_commit: Option<String>,
+    #[serde(default)]
+    pub created_date: Option<DateTime<Utc>>,
+}
+
+/// A typed `{ "$type": ..., "$value": ... }` thread property
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertyValue {
+    #[serde(rename = "$type")]
+    pub value_type: String,
+    #[serde(rename = "$value")]
+    pub value: Value,
+}
+
+/// Thread properties, keyed by their Azure DevOps names (`CodeReviewThreadType`, ...)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ThreadProperties(pub BTreeMap<String, PropertyValue>);
+
+impl ThreadProperties {
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.0.get(name).and_then(|property| property.value.as_str())
+    }
+
+    pub fn get_i64(&self, name: &str) -> Option<i64> {
+        let value = &self.0.get(name)?.value;
+        // Numbers are sometimes exported as strings
+        value.as_i64().or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+    }
+
+    /// The kind of system thread (`VoteUpdate`, `RefUpdate`, `ReviewersUpdate`, ...)
+    pub fn thread_type(&self) -> Option<&str> {
+        self.get_str("CodeReviewThreadType")
+    }
+
+    /// The head commit pushed by a `RefUpdate` thread
+    pub fn ref_new_head_commit(&self) -> Option<&str> {
+        self.get_str("CodeReviewRefNewHeadCommit")
+    }
+
+    /// The vote cast in a `VoteUpdate` thread
+    pub fn vote_result(&self) -> Option<i64> {
+        self.get_i64("CodeReviewVoteResult")
+    }
+}
+
+/// Error type for reading full PR documents from the archive
+#[derive(Debug)]
+pub enum PrDocumentError {
+    NotFound(i32),
+    Read(String),
+    Parse { path: String, error: serde_json::Error },
+    Mismatch { path: String, expected: i32, found: i32 },
+    IterationNotFound { pr_number: i32, iteration: i32 },
+    MissingCommit { pr_number: i32, iteration: i32, commit: &'static str },
+}
+
+impl From<String> for PrDocumentError {
+    fn from(err: String) -> Self {
+        PrDocumentError::Read(err)
+    }
+}
+
+impl std::fmt::Display for PrDocumentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrDocumentError::NotFound(pr_number) => write!(f, "PR #{} not found in the archive index", pr_number),
+            PrDocumentError::Read(message) => write!(f, "Failed to read PR document: {}", message),
+            // serde_json errors already name the offending field along with its line and column
+            PrDocumentError::Parse { path, error } => write!(
+                f,
+                "PR document '{}' does not match the expected format: {}",
+                path, error
+            ),
+            PrDocumentError::Mismatch { path, expected, found } => write!(
+                f,
+                "PR document '{}' contains PR #{} but was expected to contain PR #{}",
+                path, found, expected
+            ),
+            PrDocumentError::IterationNotFound { pr_number, iteration } => write!(
+                f,
+                "Iteration {} of PR #{} is not referenced by any of its threads",
+                iteration, pr_number
+            ),
+            PrDocumentError::MissingCommit { pr_number, iteration, commit } => write!(
+                f,
+                "Iteration {} of PR #{} has no {} commit",
+                iteration, pr_number, commit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PrDocumentError {}
+
+/// Parse and validate the full document of PR `pr_number` read from `path` in the archive
+pub fn parse_pr_document(path: &str, content: &str, pr_number: i32) -> Result<PrDocument, PrDocumentError> {
+    let document: PrDocument = serde_json::from_str(content).map_err(|error| PrDocumentError::Parse {
+        path: path.to_string(),
+        error,
+    })?;
+
+    if document.id != pr_number {
+        return Err(PrDocumentError::Mismatch {
+            path: path.to_string(),
+            expected: pr_number,
+            found: document.id,
+        });
+    }
+
+    Ok(document)
+}
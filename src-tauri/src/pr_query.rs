@@ -0,0 +1,632 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, TimeDelta, Utc};
+use serde::Deserialize;
+
+use crate::{PrIndexEntry, PrReviewer};
+
+/// A reviewer's vote on a PR, as recorded by Azure DevOps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReviewVote {
+    Approved,
+    ApprovedWithSuggestions,
+    NoVote,
+    WaitingForAuthor,
+    Rejected,
+}
+
+impl ReviewVote {
+    /// Convert the numeric vote stored in the PR document
+    pub fn from_value(value: i32) -> Option<Self> {
+        match value {
+            10 => Some(ReviewVote::Approved),
+            5 => Some(ReviewVote::ApprovedWithSuggestions),
+            0 => Some(ReviewVote::NoVote),
+            -5 => Some(ReviewVote::WaitingForAuthor),
+            -10 => Some(ReviewVote::Rejected),
+            _ => None,
+        }
+    }
+
+    /// Parse the vote name used in search queries (`vote:rejected`)
+    pub fn from_query(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "approved" | "approve" => Some(ReviewVote::Approved),
+            "suggestions" | "approved-with-suggestions" => Some(ReviewVote::ApprovedWithSuggestions),
+            "none" | "no-vote" => Some(ReviewVote::NoVote),
+            "waiting" | "waiting-for-author" => Some(ReviewVote::WaitingForAuthor),
+            "rejected" | "reject" => Some(ReviewVote::Rejected),
+            _ => None,
+        }
+    }
+}
+
+/// A range of instants, inclusive at the start and exclusive at the end
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct DateRange {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+}
+
+impl DateRange {
+    pub fn contains(&self, date: DateTime<Utc>) -> bool {
+        self.start.is_none_or(|start| date >= start) && self.end.is_none_or(|end| date < end)
+    }
+}
+
+/// A range of durations in seconds, inclusive at both ends
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct DurationRange {
+    pub min_seconds: Option<i64>,
+    pub max_seconds: Option<i64>,
+}
+
+impl DurationRange {
+    pub fn contains(&self, duration: TimeDelta) -> bool {
+        let seconds = duration.num_seconds();
+        self.min_seconds.is_none_or(|min| seconds >= min) && self.max_seconds.is_none_or(|max| seconds <= max)
+    }
+}
+
+/// A range of counts, inclusive at both ends
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+pub struct CountRange {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+impl CountRange {
+    pub fn contains(&self, count: u32) -> bool {
+        let count = i64::from(count);
+        self.min.is_none_or(|min| count >= min) && self.max.is_none_or(|max| count <= max)
+    }
+}
+
+/// Structured filters applied on top of the free-text search
+///
+/// All reviewer criteria must be met by the *same* reviewer, so
+/// `vote:suggestions required:yes` means "approved with suggestions by a required reviewer".
+/// Entries from older archives that lack a filtered field never match that filter.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct PrFilter {
+    /// Case-insensitive substring of the reviewer's display name, or their exact id
+    pub reviewer: Option<String>,
+    pub vote: Option<ReviewVote>,
+    pub required_reviewer: Option<bool>,
+    pub created: Option<DateRange>,
+    /// PRs without a completion date never match
+    pub completed: Option<DateRange>,
+    /// Time between creation and completion; still-open PRs are measured up to `now`
+    pub open_duration: Option<DurationRange>,
+    pub is_draft: Option<bool>,
+    /// Case-insensitive repository name
+    pub repository: Option<String>,
+    pub has_conflicts: Option<bool>,
+    pub reviewer_count: Option<CountRange>,
+    pub work_item_count: Option<CountRange>,
+    pub thread_count: Option<CountRange>,
+}
+
+impl PrFilter {
+    pub fn matches(&self, entry: &PrIndexEntry, now: DateTime<Utc>) -> bool {
+        if self.created.is_some_and(|range| !range.contains(entry.creation_date)) {
+            return false;
+        }
+
+        if self.completed.is_some_and(|range| !entry.completion_date.is_some_and(|date| range.contains(date))) {
+            return false;
+        }
+
+        let open_duration = entry.completion_date.unwrap_or(now) - entry.creation_date;
+        if self.open_duration.is_some_and(|range| !range.contains(open_duration)) {
+            return false;
+        }
+
+        if !matches_value(self.is_draft, entry.is_draft, |wanted, value| wanted == value)
+            || !matches_value(self.has_conflicts, entry.has_conflicts, |wanted, value| wanted == value)
+            || !matches_value(self.reviewer_count, entry.reviewer_count, |range, count| range.contains(count))
+            || !matches_value(self.work_item_count, entry.work_item_count, |range, count| range.contains(count))
+            || !matches_value(self.thread_count, entry.thread_count, |range, count| range.contains(count))
+        {
+            return false;
+        }
+
+        let repository = entry.repository.as_deref();
+        if self.repository.as_ref().is_some_and(|wanted| !repository.is_some_and(|value| value.eq_ignore_ascii_case(wanted))) {
+            return false;
+        }
+
+        if self.has_reviewer_criteria() {
+            return entry.reviewers.iter().any(|reviewer| self.matches_reviewer(reviewer));
+        }
+
+        true
+    }
+
+    pub fn has_reviewer_criteria(&self) -> bool {
+        self.reviewer.is_some() || self.vote.is_some() || self.required_reviewer.is_some()
+    }
+
+    fn matches_reviewer(&self, reviewer: &PrReviewer) -> bool {
+        if let Some(name) = &self.reviewer {
+            let name = name.to_lowercase();
+            if !reviewer.display_name.to_lowercase().contains(&name) && reviewer.id.to_lowercase() != name {
+                return false;
+            }
+        }
+
+        if self.vote.is_some_and(|vote| ReviewVote::from_value(reviewer.vote) != Some(vote)) {
+            return false;
+        }
+
+        self.required_reviewer.is_none_or(|required| reviewer.is_required == required)
+    }
+}
+
+// An unset criterion always matches; a set one never matches a missing value
+fn matches_value<C: Copy, V: Copy>(criterion: Option<C>, value: Option<V>, check: impl Fn(C, V) -> bool) -> bool {
+    match (criterion, value) {
+        (None, _) => true,
+        (Some(criterion), Some(value)) => check(criterion, value),
+        (Some(_), None) => false,
+    }
+}
+
+/// The index field PRs can be sorted by
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PrSortField {
+    #[default]
+    Number,
+    Created,
+    Completed,
+    OpenDuration,
+    Title,
+    Author,
+    Status,
+    Repository,
+    ReviewerCount,
+    WorkItemCount,
+    ThreadCount,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PrSort {
+    pub field: PrSortField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+impl Default for PrSort {
+    // Newest PRs first
+    fn default() -> Self {
+        PrSort { field: PrSortField::Number, descending: true }
+    }
+}
+
+impl PrSort {
+    /// Parse the sort used in search queries (`sort:created`, `sort:threads-asc`), descending by default
+    pub fn from_query(value: &str) -> Option<Self> {
+        let value = value.to_lowercase();
+        let (name, descending) = if let Some(name) = value.strip_suffix("-asc") {
+            (name, false)
+        } else if let Some(name) = value.strip_suffix("-desc") {
+            (name, true)
+        } else {
+            (value.as_str(), true)
+        };
+
+        let field = match name {
+            "number" | "id" => PrSortField::Number,
+            "created" => PrSortField::Created,
+            "completed" | "closed" => PrSortField::Completed,
+            "open" | "duration" => PrSortField::OpenDuration,
+            "title" => PrSortField::Title,
+            "author" => PrSortField::Author,
+            "status" => PrSortField::Status,
+            "repo" | "repository" => PrSortField::Repository,
+            "reviewers" => PrSortField::ReviewerCount,
+            "work-items" => PrSortField::WorkItemCount,
+            "threads" => PrSortField::ThreadCount,
+            _ => return None,
+        };
+
+        Some(PrSort { field, descending })
+    }
+
+    /// Sort entries in place; entries missing the sorted field always come last
+    pub fn apply(&self, entries: &mut [PrIndexEntry], now: DateTime<Utc>) {
+        entries.sort_by(|a, b| {
+            let ordering = match self.field {
+                PrSortField::Number => self.compare(Some(a.id), Some(b.id)),
+                PrSortField::Created => self.compare(Some(a.creation_date), Some(b.creation_date)),
+                PrSortField::Completed => self.compare(a.completion_date, b.completion_date),
+                PrSortField::OpenDuration => self.compare(
+                    Some(a.completion_date.unwrap_or(now) - a.creation_date),
+                    Some(b.completion_date.unwrap_or(now) - b.creation_date),
+                ),
+                PrSortField::Title => self.compare(Some(a.title.to_lowercase()), Some(b.title.to_lowercase())),
+                PrSortField::Author => self.compare(Some(a.created_by.to_lowercase()), Some(b.created_by.to_lowercase())),
+                PrSortField::Status => self.compare(Some(&a.status), Some(&b.status)),
+                PrSortField::Repository => self.compare(a.repository.as_ref(), b.repository.as_ref()),
+                PrSortField::ReviewerCount => self.compare(a.reviewer_count, b.reviewer_count),
+                PrSortField::WorkItemCount => self.compare(a.work_item_count, b.work_item_count),
+                PrSortField::ThreadCount => self.compare(a.thread_count, b.thread_count),
+            };
+
+            // Newest PR first among equals
+            ordering.then_with(|| b.id.cmp(&a.id))
+        });
+    }
+
+    fn compare<T: Ord>(&self, a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) if self.descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+/// A search query split into its free text and the `key:value` qualifiers it contained
+#[derive(Debug, Default)]
+pub struct PrQuery {
+    pub text: String,
+    pub filter: PrFilter,
+    pub sort: Option<PrSort>,
+}
+
+/// Split a search query into its free text and the `key:value` qualifiers it contains
+///
+/// Supported qualifiers:
+/// * `reviewer:<name>` - reviewed by someone whose name contains `<name>` (quote names with spaces)
+/// * `vote:<approved|suggestions|none|waiting|rejected>` - a reviewer cast this vote
+/// * `required:<yes|no>` - the reviewer was (not) required
+/// * `created:<dates>` / `completed:<dates>` - e.g. `2024-03`, `2024-01-01..2024-02-15`, `>=2024`, `<2023-06-01`
+/// * `open:<durations>` - how long the PR was open, e.g. `>7d`, `<12h`, `1d..2w`
+/// * `draft:<yes|no>`, `conflicts:<yes|no>`, `repo:<name>`
+/// * `reviewers:<counts>`, `work-items:<counts>`, `threads:<counts>` - e.g. `0`, `>3`, `2..5`
+/// * `sort:<field>[-asc|-desc]` - one of number, created, completed, open, title, author,
+///   status, repo, reviewers, work-items, threads
+///
+/// Dates may be given as a year, a month or a day and cover that whole period. Likewise, a bare
+/// duration covers its whole unit: `open:7d` is at least 7 days and less than 8.
+/// Anything that is not a recognized qualifier is kept as free text.
+pub fn parse_query(query: &str) -> PrQuery {
+    let mut parsed = PrQuery::default();
+    let mut text = Vec::new();
+
+    for word in split_query_words(query) {
+        let applied = match word.split_once(':') {
+            Some((key, value)) if !value.is_empty() => apply_qualifier(&mut parsed, key, value),
+            _ => false,
+        };
+
+        if !applied {
+            text.push(word);
+        }
+    }
+
+    parsed.text = text.join(" ");
+    parsed
+}
+
+// Returns whether `key` was a recognized qualifier with a valid value
+fn apply_qualifier(query: &mut PrQuery, key: &str, value: &str) -> bool {
+    let filter = &mut query.filter;
+
+    match key.to_lowercase().as_str() {
+        "reviewer" | "reviewed-by" => set(&mut filter.reviewer, Some(value.to_string())),
+        "vote" => set(&mut filter.vote, ReviewVote::from_query(value)),
+        "required" => set(&mut filter.required_reviewer, parse_bool(value)),
+        "created" => set(&mut filter.created, parse_date_range(value)),
+        "completed" | "closed" => set(&mut filter.completed, parse_date_range(value)),
+        "open" | "duration" => set(&mut filter.open_duration, parse_duration_range(value)),
+        "draft" => set(&mut filter.is_draft, parse_bool(value)),
+        "conflicts" => set(&mut filter.has_conflicts, parse_bool(value)),
+        "repo" | "repository" => set(&mut filter.repository, Some(value.to_string())),
+        "reviewers" => set(&mut filter.reviewer_count, parse_count_range(value)),
+        "work-items" => set(&mut filter.work_item_count, parse_count_range(value)),
+        "threads" => set(&mut filter.thread_count, parse_count_range(value)),
+        "sort" => set(&mut query.sort, PrSort::from_query(value)),
+        _ => false,
+    }
+}
+
+// Store a successfully parsed qualifier value, returning whether there was one
+fn set<T>(slot: &mut Option<T>, value: Option<T>) -> bool {
+    match value {
+        Some(value) => {
+            *slot = Some(value);
+            true
+        },
+        None => false,
+    }
+}
+
+// Parse `a..b`, `a..`, `..b`, `>a`, `>=a`, `<a`, `<=a` or a bare `a`, where each date
+// stands for the whole year, month or day it names
+fn parse_date_range(value: &str) -> Option<DateRange> {
+    if let Some((start, end)) = value.split_once("..") {
+        let start = match start {
+            "" => None,
+            start => Some(parse_date_period(start)?.0),
+        };
+        let end = match end {
+            "" => None,
+            end => Some(parse_date_period(end)?.1),
+        };
+        return Some(DateRange { start, end });
+    }
+
+    let range = if let Some(date) = value.strip_prefix(">=") {
+        DateRange { start: Some(parse_date_period(date)?.0), end: None }
+    } else if let Some(date) = value.strip_prefix('>') {
+        DateRange { start: Some(parse_date_period(date)?.1), end: None }
+    } else if let Some(date) = value.strip_prefix("<=") {
+        DateRange { start: None, end: Some(parse_date_period(date)?.1) }
+    } else if let Some(date) = value.strip_prefix('<') {
+        DateRange { start: None, end: Some(parse_date_period(date)?.0) }
+    } else {
+        let (start, end) = parse_date_period(value)?;
+        DateRange { start: Some(start), end: Some(end) }
+    };
+
+    Some(range)
+}
+
+// Parse `YYYY`, `YYYY-MM` or `YYYY-MM-DD` into the [start, end) instants of that period (in UTC)
+fn parse_date_period(value: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let parts: Vec<&str> = value.split('-').collect();
+    let year = parts.first()?.parse::<i32>().ok()?;
+
+    let (start, end) = match parts.len() {
+        1 => {
+            let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            (start, start.with_year(year + 1)?)
+        },
+        2 => {
+            let start = NaiveDate::from_ymd_opt(year, parts[1].parse().ok()?, 1)?;
+            (start, start.checked_add_months(Months::new(1))?)
+        },
+        3 => {
+            let start = NaiveDate::from_ymd_opt(year, parts[1].parse().ok()?, parts[2].parse().ok()?)?;
+            (start, start.succ_opt()?)
+        },
+        _ => return None,
+    };
+
+    Some((start.and_hms_opt(0, 0, 0)?.and_utc(), end.and_hms_opt(0, 0, 0)?.and_utc()))
+}
+
+fn parse_count_range(value: &str) -> Option<CountRange> {
+    let parse_count = |count: &str| {
+        let count = count.parse::<i64>().ok()?;
+        Some((count, count.checked_add(1)?))
+    };
+    parse_bounds(value, parse_count).map(|(min, max)| CountRange { min, max })
+}
+
+// A bare duration covers its whole unit as a bare date does, so `7d` is at least 7 days and less
+// than 8, `>7d` at least 8 days and `<=7d` less than 8
+fn parse_duration_range(value: &str) -> Option<DurationRange> {
+    parse_bounds(value, parse_duration_period)
+        .map(|(min_seconds, max_seconds)| DurationRange { min_seconds, max_seconds })
+}
+
+// Parse `a..b`, `a..`, `..b`, `>a`, `>=a`, `<a`, `<=a` or a bare `a` into inclusive integer bounds,
+// where `parse` turns each value into the [start, end) integers it stands for, like
+// parse_date_range does with periods
+fn parse_bounds(value: &str, parse: impl Fn(&str) -> Option<(i64, i64)>) -> Option<(Option<i64>, Option<i64>)> {
+    let first = |value: &str| parse(value).map(|(start, _)| start);
+    let last = |value: &str| parse(value).map(|(_, end)| end - 1);
+
+    if let Some((min, max)) = value.split_once("..") {
+        let min = match min {
+            "" => None,
+            min => Some(first(min)?),
+        };
+        let max = match max {
+            "" => None,
+            max => Some(last(max)?),
+        };
+        return Some((min, max));
+    }
+
+    let bounds = if let Some(min) = value.strip_prefix(">=") {
+        (Some(first(min)?), None)
+    } else if let Some(min) = value.strip_prefix('>') {
+        (Some(parse(min)?.1), None)
+    } else if let Some(max) = value.strip_prefix("<=") {
+        (None, Some(last(max)?))
+    } else if let Some(max) = value.strip_prefix('<') {
+        (None, Some(first(max)?.checked_sub(1)?))
+    } else {
+        (Some(first(value)?), Some(last(value)?))
+    };
+
+    Some(bounds)
+}
+
+// Parse durations like `12h`, `3d` or `2w` into the [start, end) seconds of that whole unit, e.g.
+// [3 days, 4 days) for `3d`
+fn parse_duration_period(value: &str) -> Option<(i64, i64)> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse::<i64>().ok()?;
+
+    let unit_seconds = match unit {
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    Some((amount.checked_mul(unit_seconds)?, amount.checked_add(1)?.checked_mul(unit_seconds)?))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "y" => Some(true),
+        "no" | "false" | "n" => Some(false),
+        _ => None,
+    }
+}
+
+// Split on whitespace, keeping double-quoted sections (`reviewer:"Omar Khan"`) together
+// and dropping the quotes themselves
+fn split_query_words(query: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        words.push(current);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: i64 = 60 * 60;
+    const DAY: i64 = 24 * HOUR;
+
+    fn date(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value).unwrap().with_timezone(&Utc)
+    }
+
+    fn dates(range: DateRange) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        (range.start, range.end)
+    }
+
+    fn durations(value: &str) -> Option<(Option<i64>, Option<i64>)> {
+        parse_duration_range(value).map(|range| (range.min_seconds, range.max_seconds))
+    }
+
+    fn counts(value: &str) -> Option<(Option<i64>, Option<i64>)> {
+        parse_count_range(value).map(|range| (range.min, range.max))
+    }
+
+    #[test]
+    fn splits_qualifiers_from_the_free_text() {
+        let query = parse_query("fix login reviewer:\"Omar Khan\" vote:approved draft:no sort:created-asc");
+
+        assert_eq!(query.text, "fix login");
+        assert_eq!(query.filter.reviewer.as_deref(), Some("Omar Khan"));
+        assert_eq!(query.filter.vote, Some(ReviewVote::Approved));
+        assert_eq!(query.filter.is_draft, Some(false));
+        let sort = query.sort.unwrap();
+        assert_eq!((sort.field, sort.descending), (PrSortField::Created, false));
+    }
+
+    #[test]
+    fn keeps_unknown_and_invalid_qualifiers_as_text() {
+        let query = parse_query("http://example.com vote:maybe created:yesterday threads: label:bug");
+
+        assert_eq!(query.text, "http://example.com vote:maybe created:yesterday threads: label:bug");
+        assert!(query.filter.vote.is_none());
+        assert!(query.filter.created.is_none());
+        assert!(query.filter.thread_count.is_none());
+    }
+
+    #[test]
+    fn parses_dates_as_whole_periods() {
+        let range = parse_date_range("2024").unwrap();
+        assert_eq!(dates(range), (Some(date("2024-01-01T00:00:00Z")), Some(date("2025-01-01T00:00:00Z"))));
+
+        let range = parse_date_range("2024-02").unwrap();
+        assert_eq!(dates(range), (Some(date("2024-02-01T00:00:00Z")), Some(date("2024-03-01T00:00:00Z"))));
+
+        let range = parse_date_range("2024-02-29").unwrap();
+        assert_eq!(dates(range), (Some(date("2024-02-29T00:00:00Z")), Some(date("2024-03-01T00:00:00Z"))));
+
+        assert!(parse_date_range("2023-02-29").is_none());
+        assert!(parse_date_range("2024-13").is_none());
+        assert!(parse_date_range("last-week").is_none());
+    }
+
+    #[test]
+    fn parses_date_bounds_around_whole_periods() {
+        let (start, end) = (Some(date("2024-03-01T00:00:00Z")), Some(date("2024-04-01T00:00:00Z")));
+
+        assert_eq!(dates(parse_date_range(">=2024-03").unwrap()), (start, None));
+        assert_eq!(dates(parse_date_range(">2024-03").unwrap()), (end, None));
+        assert_eq!(dates(parse_date_range("<=2024-03").unwrap()), (None, end));
+        assert_eq!(dates(parse_date_range("<2024-03").unwrap()), (None, start));
+        assert_eq!(
+            dates(parse_date_range("2024-01-15..2024-03").unwrap()),
+            (Some(date("2024-01-15T00:00:00Z")), end)
+        );
+        assert_eq!(dates(parse_date_range("2024-03..").unwrap()), (start, None));
+        assert_eq!(dates(parse_date_range("..2024-03").unwrap()), (None, end));
+    }
+
+    #[test]
+    fn parses_durations_as_whole_units() {
+        assert_eq!(durations("7d"), Some((Some(7 * DAY), Some(8 * DAY - 1))));
+        assert_eq!(durations("12h"), Some((Some(12 * HOUR), Some(13 * HOUR - 1))));
+        assert_eq!(durations("2w"), Some((Some(14 * DAY), Some(21 * DAY - 1))));
+
+        assert!(durations("7").is_none());
+        assert!(durations("7m").is_none());
+        assert!(durations("d").is_none());
+        assert!(durations("99999999999999999w").is_none());
+    }
+
+    #[test]
+    fn parses_duration_bounds_consistently_with_bare_durations() {
+        // `7d` is [7d, 8d), which the bounds either include whole or leave out whole
+        assert_eq!(durations(">=7d"), Some((Some(7 * DAY), None)));
+        assert_eq!(durations(">7d"), Some((Some(8 * DAY), None)));
+        assert_eq!(durations("<=7d"), Some((None, Some(8 * DAY - 1))));
+        assert_eq!(durations("<7d"), Some((None, Some(7 * DAY - 1))));
+        assert_eq!(durations("1d..2w"), Some((Some(DAY), Some(21 * DAY - 1))));
+        assert_eq!(durations("..7d"), Some((None, Some(8 * DAY - 1))));
+        assert_eq!(durations("7d.."), Some((Some(7 * DAY), None)));
+    }
+
+    #[test]
+    fn parses_counts() {
+        assert_eq!(counts("3"), Some((Some(3), Some(3))));
+        assert_eq!(counts(">3"), Some((Some(4), None)));
+        assert_eq!(counts(">=3"), Some((Some(3), None)));
+        assert_eq!(counts("<3"), Some((None, Some(2))));
+        assert_eq!(counts("<=3"), Some((None, Some(3))));
+        assert_eq!(counts("2..5"), Some((Some(2), Some(5))));
+        assert_eq!(counts("2.."), Some((Some(2), None)));
+        assert_eq!(counts("..5"), Some((None, Some(5))));
+
+        assert!(counts("many").is_none());
+        assert!(counts(">9223372036854775807").is_none());
+        assert!(counts("<-9223372036854775808").is_none());
+    }
+
+    #[test]
+    fn matches_open_durations_of_the_whole_unit() {
+        let range = parse_duration_range("7d").unwrap();
+
+        assert!(!range.contains(TimeDelta::seconds(7 * DAY - 1)));
+        assert!(range.contains(TimeDelta::seconds(7 * DAY)));
+        assert!(range.contains(TimeDelta::seconds(8 * DAY - 1)));
+        assert!(!range.contains(TimeDelta::seconds(8 * DAY)));
+    }
+}
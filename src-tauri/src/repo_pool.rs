@@ -0,0 +1,233 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+
+use git2::Repository;
+
+/// A pool of handles to the selected repository, so that git reads run in parallel instead of
+/// waiting on each other. A `Repository` can't be shared between threads, so each read takes a
+/// handle of its own, opened on demand and reused afterwards.
+pub struct RepoPool {
+    state: Mutex<PoolState>,
+    // Signaled whenever a handle is returned or the repository changes
+    returned: Condvar,
+    max_handles: usize,
+}
+
+struct PoolState {
+    path: Option<PathBuf>,
+    // Incremented when the repository changes, so that handles of the previous one are dropped
+    // instead of returned
+    generation: u64,
+    idle: Vec<Repository>,
+    in_use: usize,
+}
+
+/// A handle taken from the pool, returned to it when dropped
+pub struct PooledRepo<'a> {
+    pool: &'a RepoPool,
+    repo: Option<Repository>,
+    generation: u64,
+}
+
+impl RepoPool {
+    pub fn new(max_handles: usize) -> Self {
+        RepoPool {
+            state: Mutex::new(PoolState {
+                path: None,
+                generation: 0,
+                idle: Vec::new(),
+                in_use: 0,
+            }),
+            returned: Condvar::new(),
+            max_handles: max_handles.max(1),
+        }
+    }
+
+    // Switch the pool to another repository, after checking that it can be opened. Handles of the
+    // previous repository still in use are dropped when done with, and don't count towards the cap.
+    pub fn set_repository(&self, path: &Path) -> Result<(), git2::Error> {
+        self.set_opened_repository(Repository::open(path)?);
+        Ok(())
+    }
+
+    // Switch the pool to a repository already opened, which becomes its first handle
+    pub fn set_opened_repository(&self, repo: Repository) {
+        let mut state = self.state.lock().unwrap();
+        state.path = Some(repo.path().to_path_buf());
+        state.generation += 1;
+        state.idle = vec![repo];
+        state.in_use = 0;
+        drop(state);
+
+        self.returned.notify_all();
+    }
+
+    // Path of the repository's git directory, if one is selected
+    pub fn path(&self) -> Option<PathBuf> {
+        self.state.lock().unwrap().path.clone()
+    }
+
+    // Take a handle to the repository, waiting for one to be returned if all of them are in use
+    pub fn get(&self) -> Result<PooledRepo<'_>, String> {
+        let start = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            let path = state.path.clone().ok_or("No repository selected")?;
+
+            if let Some(repo) = state.idle.pop() {
+                state.in_use += 1;
+                return Ok(PooledRepo { pool: self, repo: Some(repo), generation: state.generation });
+            }
+
+            if state.in_use < self.max_handles {
+                // Opening a repository only reads its config, so it is done without holding the
+                // lock. The slot is reserved first so that the cap holds meanwhile.
+                state.in_use += 1;
+                let generation = state.generation;
+                drop(state);
+
+                return match Repository::open(&path) {
+                    Ok(repo) => {
+                        println!("Performance: opened repository handle in {:?}", start.elapsed());
+                        Ok(PooledRepo { pool: self, repo: Some(repo), generation })
+                    },
+                    Err(e) => {
+                        self.release(None, generation);
+                        Err(format!("Failed to open git repository: {}", e))
+                    },
+                };
+            }
+
+            state = self.returned.wait(state).unwrap();
+        }
+    }
+
+    fn release(&self, repo: Option<Repository>, generation: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.generation == generation {
+            state.in_use -= 1;
+            state.idle.extend(repo);
+        }
+        drop(state);
+
+        self.returned.notify_one();
+    }
+}
+
+impl Deref for PooledRepo<'_> {
+    type Target = Repository;
+
+    fn deref(&self) -> &Repository {
+        self.repo.as_ref().unwrap()
+    }
+}
+
+impl Drop for PooledRepo<'_> {
+    fn drop(&mut self) {
+        self.pool.release(self.repo.take(), self.generation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    // Long enough for a waiting thread to have been woken up if it was going to be
+    const WAIT: Duration = Duration::from_millis(200);
+
+    fn init_repo() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let git_dir = Repository::init(dir.path()).unwrap().path().to_path_buf();
+        (dir, git_dir)
+    }
+
+    // Take a handle on another thread, reporting the path of its repository once taken
+    fn get_in_background<'scope>(scope: &'scope thread::Scope<'scope, '_>, pool: &'scope RepoPool) -> mpsc::Receiver<PathBuf> {
+        let (sender, receiver) = mpsc::channel();
+        scope.spawn(move || {
+            let repo = pool.get().unwrap();
+            sender.send(repo.path().to_path_buf()).unwrap();
+        });
+        receiver
+    }
+
+    #[test]
+    fn fails_without_a_repository() {
+        let pool = RepoPool::new(2);
+
+        assert!(pool.get().is_err());
+        assert_eq!(pool.path(), None);
+    }
+
+    #[test]
+    fn reuses_returned_handles() {
+        let (_dir, git_dir) = init_repo();
+        let pool = RepoPool::new(2);
+        pool.set_repository(&git_dir).unwrap();
+
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        assert_eq!(first.path(), git_dir);
+        assert_eq!(second.path(), git_dir);
+        drop(first);
+        drop(second);
+
+        assert_eq!(pool.state.lock().unwrap().idle.len(), 2);
+        let _third = pool.get().unwrap();
+        assert_eq!(pool.state.lock().unwrap().idle.len(), 1);
+    }
+
+    #[test]
+    fn waits_for_a_handle_to_be_returned_at_the_cap() {
+        let (_dir, git_dir) = init_repo();
+        let pool = RepoPool::new(1);
+        pool.set_repository(&git_dir).unwrap();
+
+        let held = pool.get().unwrap();
+        thread::scope(|scope| {
+            let taken = get_in_background(scope, &pool);
+            assert!(taken.recv_timeout(WAIT).is_err());
+
+            drop(held);
+            assert_eq!(taken.recv_timeout(Duration::from_secs(10)).unwrap(), git_dir);
+        });
+    }
+
+    #[test]
+    fn drops_the_handles_of_the_previous_repository() {
+        let (_first_dir, first_git_dir) = init_repo();
+        let (_second_dir, second_git_dir) = init_repo();
+        let pool = RepoPool::new(1);
+        pool.set_repository(&first_git_dir).unwrap();
+
+        // Handles of the previous repository still in use don't count towards the cap
+        let old = pool.get().unwrap();
+        pool.set_repository(&second_git_dir).unwrap();
+        let new = pool.get().unwrap();
+        assert_eq!(new.path(), second_git_dir);
+
+        thread::scope(|scope| {
+            let taken = get_in_background(scope, &pool);
+
+            // Returning the old handle neither frees a slot nor joins the pool
+            drop(old);
+            assert!(taken.recv_timeout(WAIT).is_err());
+            assert!(pool.state.lock().unwrap().idle.is_empty());
+
+            drop(new);
+            assert_eq!(taken.recv_timeout(Duration::from_secs(10)).unwrap(), second_git_dir);
+        });
+
+        let state = pool.state.lock().unwrap();
+        assert_eq!(state.in_use, 0);
+        assert_eq!(state.idle.len(), 1);
+        assert_eq!(state.idle[0].path(), second_git_dir);
+    }
+}
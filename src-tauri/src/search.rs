@@ -1,47 +1,177 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 use std::time::Instant;
 
-use crate::PrIndexEntry;
-use probly_search::Index;
+use crate::{PrIndexEntry, PrReviewer};
+use probly_search::{FieldAccessor, Index};
+use serde::{Deserialize, Serialize};
+
+/// Options controlling how titles, descriptions and comments are split into tokens
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TokenizerOptions {
+    /// Split camelCase and PascalCase words into their parts (`retryAsync` -> `retry`, `async`)
+    pub split_case: bool,
+    /// Split dotted and `::` qualified identifiers into their segments. snake_case identifiers
+    /// are always split at their underscores.
+    pub split_qualified: bool,
+    /// Keep the whole identifier as a token alongside its parts
+    pub keep_whole: bool,
+}
+
+impl TokenizerOptions {
+    pub const DEFAULT: TokenizerOptions = TokenizerOptions {
+        split_case: true,
+        split_qualified: true,
+        keep_whole: true,
+    };
+}
+
+impl Default for TokenizerOptions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The parts of a full PR document that are indexed in addition to the index entry
+#[derive(Debug, Deserialize)]
+pub struct PrSearchDetails {
+    pub id: i32,
+    #[serde(default)]
+    pub reviewers: Vec<PrReviewer>,
+    #[serde(default)]
+    pub threads: Vec<ThreadText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ThreadText {
+    #[serde(default)]
+    pub comments: Vec<CommentText>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentText {
+    pub content: Option<String>,
+    #[serde(rename = "commentType")]
+    pub comment_type: Option<String>,
+}
+
+impl PrSearchDetails {
+    // Collect the text of all user comments, skipping system messages (votes, pushes, etc.)
+    pub fn comments(self) -> Vec<String> {
+        self.threads
+            .into_iter()
+            .flat_map(|thread| thread.comments)
+            .filter(|comment| comment.comment_type.as_deref() != Some("system"))
+            .filter_map(|comment| comment.content)
+            .collect()
+    }
+}
+
+// The document handed to probly-search for each PR. probly-search takes its tokenizer as a plain
+// fn pointer, which can't see the index's options, so the fields are tokenized beforehand into
+// space-separated tokens
+struct SearchDocument {
+    title: String,
+    author: String,
+    description: String,
+    comments: Vec<String>,
+}
 
 pub struct SearchIndex {
-    // Index with 2 fields: title and author
+    // Index with 4 fields: title, author, description and comments
     index: Mutex<Option<Index<usize>>>,
-    pr_map: Mutex<HashMap<usize, PrIndexEntry>>,
+    // The indexed PRs by document id, loaded from the index file before the index is built
+    pr_map: Mutex<Option<HashMap<usize, PrIndexEntry>>>,
+    // Held while the index is built, so that a single build runs at a time
+    building: Mutex<()>,
+    tokenizer_options: RwLock<TokenizerOptions>,
 }
 
 impl SearchIndex {
     pub fn new() -> Self {
         SearchIndex {
             index: Mutex::new(None),
-            pr_map: Mutex::new(HashMap::new()),
+            pr_map: Mutex::new(None),
+            building: Mutex::new(()),
+            tokenizer_options: RwLock::new(TokenizerOptions::DEFAULT),
         }
     }
 
-    // Reset and rebuild the search index with new PR entries
-    pub fn rebuild_index(&self, entries: &[PrIndexEntry]) -> Result<(), String> {
+    // Load the PR entries given by `load`, unless they are already loaded. The entries only get
+    // the reviewers of their full documents once the index is built.
+    pub fn load_entries(&self, load: impl FnOnce() -> Result<Vec<PrIndexEntry>, String>) -> Result<(), String> {
+        let mut pr_map = self.pr_map.lock().unwrap();
+        if pr_map.is_none() {
+            *pr_map = Some(load()?.into_iter().enumerate().collect());
+        }
+
+        Ok(())
+    }
+
+    // Build the index from the entries and details given by `load`, unless it is already built.
+    // Callers arriving during a build wait for it instead of starting their own.
+    pub fn ensure_built(
+        &self,
+        load: impl FnOnce() -> Result<(Vec<PrIndexEntry>, HashMap<i32, PrSearchDetails>), String>,
+    ) -> Result<(), String> {
+        let _building = self.building.lock().unwrap();
+        if self.is_initialized() {
+            return Ok(());
+        }
+
+        let (entries, details) = load()?;
+        self.rebuild_index(&entries, details)
+    }
+
+    // Reset and rebuild the search index with new PR entries and the details of their
+    // full documents (keyed by PR number)
+    pub fn rebuild_index(
+        &self,
+        entries: &[PrIndexEntry],
+        mut details: HashMap<i32, PrSearchDetails>,
+    ) -> Result<(), String> {
         let start = Instant::now();
+        let options = self.tokenizer_options();
+        let tokens = |text: &str| tokenize(text, options).join(" ");
 
-        // Create a new search index with 2 fields (title, author)
-        let mut index = Index::<usize>::new(2);
+        // Create a new search index with 4 fields (title, author, description, comments)
+        let mut index = Index::<usize>::new(4);
         let mut pr_map = HashMap::new();
 
+        let extractors: [FieldAccessor<SearchDocument>; 4] =
+            [title_extract, author_extract, description_extract, comments_extract];
+
         // Add each PR to the index
         for (i, entry) in entries.iter().enumerate() {
             let doc_id = i;
 
-            // Store the PR for later retrieval
-            pr_map.insert(doc_id, entry.clone());
+            let mut entry = entry.clone();
+            let mut comments = Vec::new();
+
+            // Reviewers only exist in the full document, so they are attached to the entry here
+            if let Some(mut pr_details) = details.remove(&entry.id) {
+                entry.reviewers = std::mem::take(&mut pr_details.reviewers);
+                comments = pr_details.comments();
+            }
+
+            let document = SearchDocument {
+                title: tokens(&entry.title),
+                author: tokens(&entry.created_by),
+                description: entry.description.as_deref().map(tokens).unwrap_or_default(),
+                comments: comments.iter().map(|comment| tokens(comment)).collect(),
+            };
 
             // Add to search index
             index.add_document(
-                &[title_extract, author_extract],
-                tokenizer,
+                &extractors,
+                split_tokens,
                 doc_id,
-                entry,
+                &document,
             );
+
+            // Store the PR for later retrieval
+            pr_map.insert(doc_id, entry);
         }
 
         // Update the stored index and map
@@ -50,7 +180,7 @@ impl SearchIndex {
             *index_guard = Some(index);
 
             let mut pr_map_guard = self.pr_map.lock().unwrap();
-            *pr_map_guard = pr_map;
+            *pr_map_guard = Some(pr_map);
         }
 
         println!("Performance: built search index for {} entries in {:?}",
@@ -70,8 +200,11 @@ impl SearchIndex {
             None => return Err("Search index not initialized".to_string()),
         };
 
-        // Get the PR map
-        let pr_map = self.pr_map.lock().unwrap();
+        // Get the PR map, which the index was built with
+        let pr_map_guard = self.pr_map.lock().unwrap();
+        let Some(pr_map) = &*pr_map_guard else {
+            return Err("Search index not initialized".to_string());
+        };
 
         let mut matched_prs = Vec::new();
         let query_trimmed = query.trim();
@@ -103,14 +236,15 @@ impl SearchIndex {
 
         // If we still don't have matches, do a text search
         if matched_prs.is_empty() {
-            // Field weights: title=1.0, author=0.5
-            let field_weights = &[1.0, 1.0];
+            // Field weights: title=1.0, author=1.0, description=0.5, comments=0.25
+            let field_weights = &[1.0, 1.0, 0.5, 0.25];
 
-            // Search with scoring
+            // Search with scoring, for the tokens of the query as the index has them
+            let query_tokens = tokenize(query_trimmed, self.tokenizer_options()).join(" ");
             let results = index.query(
-                query_trimmed,
+                &query_tokens,
                 &mut probly_search::score::zero_to_one::new(),
-                tokenizer,
+                split_tokens,
                 field_weights,
             );
 
@@ -128,25 +262,236 @@ impl SearchIndex {
         Ok(matched_prs)
     }
 
+    // All loaded PRs, including the reviewers attached from their full documents once the index
+    // is built
+    pub fn entries(&self) -> Vec<PrIndexEntry> {
+        self.pr_map.lock().unwrap()
+            .iter()
+            .flat_map(HashMap::values)
+            .cloned()
+            .collect()
+    }
+
+    // Look up a loaded PR by its number
+    pub fn entry(&self, pr_number: i32) -> Option<PrIndexEntry> {
+        self.pr_map.lock().unwrap()
+            .as_ref()?
+            .values()
+            .find(|entry| entry.id == pr_number)
+            .cloned()
+    }
+
     // Check if the index is initialized
     pub fn is_initialized(&self) -> bool {
         self.index.lock().unwrap().is_some()
     }
+
+    // Drop the built index and the loaded entries so that they are reloaded on next use, once
+    // the build in progress, if any, is done
+    pub fn clear(&self) {
+        let _building = self.building.lock().unwrap();
+        *self.index.lock().unwrap() = None;
+        *self.pr_map.lock().unwrap() = None;
+    }
+
+    pub fn tokenizer_options(&self) -> TokenizerOptions {
+        *self.tokenizer_options.read().unwrap()
+    }
+
+    // Changing the tokenizer invalidates the index, so it is dropped to force a rebuild. The
+    // entries stay valid.
+    pub fn set_tokenizer_options(&self, options: TokenizerOptions) {
+        let _building = self.building.lock().unwrap();
+        *self.tokenizer_options.write().unwrap() = options;
+        *self.index.lock().unwrap() = None;
+    }
+}
+
+// The tokenizer given to probly-search, for text already tokenized with `tokenize`
+fn split_tokens(s: &str) -> Vec<Cow<'_, str>> {
+    s.split_whitespace().map(Cow::from).collect()
+}
+
+// Split on whitespace and special characters, then break code identifiers
+// (`OrderServiceClient.retryAsync`, `search::tokenizer`, `retry_count`) into their parts
+fn tokenize(s: &str, options: TokenizerOptions) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    // '.', ':' and '_' are kept here so that identifiers stay together as one word
+    let words = s
+        .split(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | ':' | '_')))
+        .map(|word| word.trim_matches(['.', ':', '_']))
+        .filter(|word| !word.is_empty());
+
+    // Each split breaks the parts of the previous one further, e.g. `order_client.retryAsync`
+    // into `order_client` and `retryAsync`, then `order`, `client`, `retry` and `async`
+    let mut splits: Vec<fn(&str) -> Vec<&str>> = Vec::new();
+    if options.split_qualified {
+        splits.push(|word| split_on(word, &['.', ':']));
+    }
+    splits.push(|word| split_on(word, &['_']));
+    if options.split_case {
+        splits.push(split_case);
+    }
+
+    for word in words {
+        let mut parts = vec![word];
+        for split in &splits {
+            parts = parts.into_iter()
+                .flat_map(|part| {
+                    let split_parts = split(part);
+                    if options.keep_whole && split_parts.len() > 1 {
+                        tokens.push(part.to_lowercase());
+                    }
+                    split_parts
+                })
+                .collect();
+        }
+
+        tokens.extend(parts.into_iter().map(str::to_lowercase));
+    }
+
+    tokens
 }
 
-// Tokenizer function - split on whitespace and special characters
-fn tokenizer(s: &str) -> Vec<Cow<str>> {
-    s.split(|c: char| c.is_whitespace() || c == '-' || c == '_' || c == '/')
-        .filter(|s| !s.is_empty())
-        .map(|s| Cow::from(s.to_lowercase()))
+// Split a word on separator characters, dropping the empty parts between repeated separators
+fn split_on<'a>(word: &'a str, separators: &[char]) -> Vec<&'a str> {
+    word.split(separators)
+        .filter(|part| !part.is_empty())
         .collect()
 }
 
+// Split a camelCase or PascalCase word into its parts, keeping acronyms together
+// (`HTTPServerError` -> `HTTP`, `Server`, `Error`)
+fn split_case(word: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+
+    for i in 1..chars.len() {
+        let (index, current) = chars[i];
+        let previous = chars[i - 1].1;
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        let boundary = current.is_uppercase()
+            && (previous.is_lowercase()
+                || previous.is_numeric()
+                || (previous.is_uppercase() && next.is_some_and(char::is_lowercase)));
+
+        if boundary {
+            parts.push(&word[part_start..index]);
+            part_start = index;
+        }
+    }
+
+    parts.push(&word[part_start..]);
+    parts
+}
+
 // Field extraction functions
-fn title_extract(pr: &PrIndexEntry) -> Vec<&str> {
-    vec![pr.title.as_str()]
+fn title_extract(doc: &SearchDocument) -> Vec<&str> {
+    vec![doc.title.as_str()]
+}
+
+fn author_extract(doc: &SearchDocument) -> Vec<&str> {
+    vec![doc.author.as_str()]
 }
 
-fn author_extract(pr: &PrIndexEntry) -> Vec<&str> {
-    vec![pr.created_by.as_str()]
-}
\ No newline at end of file
+fn description_extract(doc: &SearchDocument) -> Vec<&str> {
+    vec![doc.description.as_str()]
+}
+
+fn comments_extract(doc: &SearchDocument) -> Vec<&str> {
+    doc.comments.iter().map(String::as_str).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i32) -> PrIndexEntry {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": format!("PR {}", id),
+            "created_by": "Author",
+            "creation_date": "2024-01-01T00:00:00Z",
+            "status": "completed",
+            "source_branch": "refs/heads/feature",
+            "target_branch": "refs/heads/main",
+            "filename": format!("{}.json", id),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn loads_entries_without_building_the_index() {
+        let index = SearchIndex::new();
+        index.load_entries(|| Ok(vec![entry(1), entry(2)])).unwrap();
+
+        assert!(!index.is_initialized());
+        assert_eq!(index.entries().len(), 2);
+        assert_eq!(index.entry(2).map(|entry| entry.filename), Some("2.json".to_string()));
+
+        // Loaded entries are kept until cleared
+        index.load_entries(|| panic!("entries loaded twice")).unwrap();
+        index.clear();
+        assert!(index.entries().is_empty());
+        assert!(index.entry(2).is_none());
+    }
+
+    #[test]
+    fn builds_the_index_once() {
+        let index = SearchIndex::new();
+        index.ensure_built(|| Ok((vec![entry(1)], HashMap::new()))).unwrap();
+        index.ensure_built(|| panic!("index built twice")).unwrap();
+
+        assert!(index.is_initialized());
+        assert_eq!(index.search("1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_entries_when_the_tokenizer_changes() {
+        let index = SearchIndex::new();
+        index.ensure_built(|| Ok((vec![entry(1)], HashMap::new()))).unwrap();
+
+        index.set_tokenizer_options(TokenizerOptions { split_case: false, ..TokenizerOptions::DEFAULT });
+        assert!(!index.is_initialized());
+        assert!(index.search("1").is_err());
+        assert_eq!(index.entries().len(), 1);
+    }
+
+    #[test]
+    fn splits_text_on_whitespace_and_punctuation() {
+        assert_eq!(tokenize("Fix the build, (again)!", TokenizerOptions::DEFAULT), ["fix", "the", "build", "again"]);
+    }
+
+    #[test]
+    fn splits_identifiers_into_their_parts() {
+        assert_eq!(tokenize("retryAsync", TokenizerOptions::DEFAULT), ["retryasync", "retry", "async"]);
+        assert_eq!(tokenize("HTTPServerError", TokenizerOptions::DEFAULT), ["httpservererror", "http", "server", "error"]);
+        assert_eq!(
+            tokenize("search::tokenizer.", TokenizerOptions::DEFAULT),
+            ["search::tokenizer", "search", "tokenizer"]
+        );
+    }
+
+    #[test]
+    fn splits_snake_case_identifiers_keeping_them_whole() {
+        assert_eq!(tokenize("retry_count", TokenizerOptions::DEFAULT), ["retry_count", "retry", "count"]);
+        assert_eq!(tokenize("__init__", TokenizerOptions::DEFAULT), ["init"]);
+        assert_eq!(
+            tokenize("order_client.retryAsync", TokenizerOptions::DEFAULT),
+            ["order_client.retryasync", "order_client", "retryasync", "order", "client", "retry", "async"]
+        );
+    }
+
+    #[test]
+    fn follows_the_options() {
+        let options = TokenizerOptions { split_case: false, split_qualified: false, keep_whole: true };
+        assert_eq!(tokenize("Order.retryAsync", options), ["order.retryasync"]);
+        assert_eq!(tokenize("retry_count", options), ["retry_count", "retry", "count"]);
+
+        let options = TokenizerOptions { keep_whole: false, ..TokenizerOptions::DEFAULT };
+        assert_eq!(tokenize("Order.retry_count", options), ["order", "retry", "count"]);
+    }
+}
@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use git2::Repository;
+use serde::Serialize;
+
+use crate::git_cache::GitCache;
+use crate::git_lines::{get_file_diff_between_revisions, get_file_lines_at_revision, DiffLineInfo};
+use crate::pr_document::{FilePosition, IterationDetails, PrDocument, Thread};
+
+/// The code a thread comments on, at the iteration it was made on
+#[derive(Debug, Serialize)]
+pub struct ThreadSnippet {
+    pub thread_id: i64,
+    /// Path of the file within the repository
+    pub file_path: String,
+    /// The commit the diff is from, for threads on the changed side of the file
+    pub from_commit: Option<String>,
+    /// The commit the thread's lines are numbered in
+    pub to_commit: Option<String>,
+    /// The lines of the thread (1-based, inclusive)
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The diff of the lines with context, for threads on the changed side of the file
+    pub diff: Option<Vec<DiffLineInfo>>,
+    /// The lines themselves, for threads on the original side of the file (deleted lines)
+    pub lines: Option<Vec<String>>,
+    /// Why the snippet couldn't be read, e.g. a missing commit
+    pub error: Option<String>,
+}
+
+// Which side of the diff a thread's lines are on
+enum ThreadSide {
+    // The file at the iteration's source commit
+    Right,
+    // The file at the iteration's target commit
+    Left,
+}
+
+/// Read the code each thread of a PR comments on, in a single pass so that the threads on the same
+/// file share one diff. Threads that aren't on lines of a file have no snippet.
+///
+/// Lines on the right side are diffed between the target and source commits of the thread's first
+/// iteration, as the threads view always did. Lines on the left side only exist at the target
+/// commit, so they are read from there.
+///
+/// # Arguments
+///
+/// * `repo` - The Git repository
+/// * `cache` - Cache of the diffs and lines of files, shared by all the requests
+/// * `document` - The PR, with its threads
+/// * `context_lines` - Number of lines around each diffed range to include as well
+///
+pub fn get_thread_snippets(
+    repo: &Repository,
+    cache: &GitCache,
+    document: &PrDocument,
+    context_lines: u32,
+) -> Vec<ThreadSnippet> {
+    document.threads.iter()
+        .filter(|thread| !thread.is_deleted)
+        .filter_map(|thread| {
+            let context = thread.thread_context.as_ref()?;
+            let (side, lines) = match (context.right_file_start, context.right_file_end) {
+                (Some(start), Some(end)) => (ThreadSide::Right, line_range(start, end)),
+                _ => (ThreadSide::Left, line_range(context.left_file_start?, context.left_file_end?)),
+            };
+
+            // git2 wants paths relative to the repository, while the archive's start with '/'
+            let file_path = context.file_path.trim_start_matches('/').to_string();
+            Some(thread_snippet(repo, cache, document, thread, file_path, side, lines, context_lines))
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn thread_snippet(
+    repo: &Repository,
+    cache: &GitCache,
+    document: &PrDocument,
+    thread: &Thread,
+    file_path: String,
+    side: ThreadSide,
+    lines: Range<usize>,
+    context_lines: u32,
+) -> ThreadSnippet {
+    let mut snippet = ThreadSnippet {
+        thread_id: thread.id,
+        file_path,
+        from_commit: None,
+        to_commit: None,
+        start_line: lines.start,
+        end_line: lines.end,
+        diff: None,
+        lines: None,
+        error: None,
+    };
+
+    let Some(iteration) = thread_iteration(thread) else {
+        snippet.error = Some(format!("Thread {} of PR #{} has no iteration", thread.id, document.id));
+        return snippet;
+    };
+    let missing = |commit: &str| format!("Iteration {} of PR #{} has no {} commit", iteration.id, document.id, commit);
+
+    let Some(target_commit) = iteration.target_commit.clone() else {
+        snippet.error = Some(missing("target"));
+        return snippet;
+    };
+
+    let result = match side {
+        ThreadSide::Right => {
+            let Some(source_commit) = iteration.source_commit.clone() else {
+                snippet.error = Some(missing("source"));
+                return snippet;
+            };
+
+            let result = get_file_diff_between_revisions(
+                repo,
+                cache,
+                &snippet.file_path,
+                &target_commit,
+                &source_commit,
+                lines,
+                context_lines,
+            )
+                .map(|diff| snippet.diff = Some(diff))
+                .map_err(|err| err.to_string());
+            snippet.from_commit = Some(target_commit);
+            snippet.to_commit = Some(source_commit);
+            result
+        },
+        ThreadSide::Left => {
+            let result = get_file_lines_at_revision(
+                repo,
+                cache,
+                &snippet.file_path,
+                &target_commit,
+                lines,
+                false,
+            )
+                .map(|file_lines| snippet.lines = Some(file_lines.lines))
+                .map_err(|err| err.to_string());
+            snippet.to_commit = Some(target_commit);
+            result
+        },
+    };
+
+    snippet.error = result.err();
+    snippet
+}
+
+// The iteration a thread's code is read at
+fn thread_iteration(thread: &Thread) -> Option<&IterationDetails> {
+    thread.pull_request_thread_context.as_ref()?
+        .first_iteration_details.as_ref()
+}
+
+// Lines of a thread as a range of line numbers, whichever way they were selected
+fn line_range(start: FilePosition, end: FilePosition) -> Range<usize> {
+    let (start, end) = (start.line as usize, end.line as usize);
+    start.min(end)..start.max(end)
+}
@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+
+use crate::git_diff::LineDiff;
+
+// Lines with more tokens than this are marked as changed as a whole, to bound the LCS table
+const MAX_TOKENS: usize = 500;
+
+/// A changed range within a line, in UTF-16 code units as used by JavaScript strings
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A word, a run of whitespace or a single punctuation character, with its UTF-16 range
+struct Token<'a> {
+    text: &'a str,
+    start: usize,
+    end: usize,
+}
+
+#[derive(PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Space,
+    Other,
+}
+
+fn token_kind(c: char) -> TokenKind {
+    if c.is_alphanumeric() || c == '_' {
+        TokenKind::Word
+    } else if c.is_whitespace() {
+        TokenKind::Space
+    } else {
+        TokenKind::Other
+    }
+}
+
+fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut utf16_offset = 0;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((byte_start, c)) = chars.next() {
+        let kind = token_kind(c);
+        let mut byte_end = byte_start + c.len_utf8();
+        let mut utf16_end = utf16_offset + c.len_utf16();
+
+        // Words and whitespace extend over runs of the same kind, other characters stand alone
+        if kind != TokenKind::Other {
+            while let Some(&(_, next)) = chars.peek() {
+                if token_kind(next) != kind {
+                    break;
+                }
+                byte_end += next.len_utf8();
+                utf16_end += next.len_utf16();
+                chars.next();
+            }
+        }
+
+        tokens.push(Token { text: &line[byte_start..byte_end], start: utf16_offset, end: utf16_end });
+        utf16_offset = utf16_end;
+    }
+
+    tokens
+}
+
+/// Merge the ranges of the tokens not matched by the LCS into contiguous changed ranges
+fn changed_ranges(tokens: &[Token], matched: &[bool]) -> Vec<ChangedRange> {
+    let mut ranges: Vec<ChangedRange> = Vec::new();
+
+    for (token, _) in tokens.iter().zip(matched).filter(|(_, matched)| !**matched) {
+        match ranges.last_mut() {
+            Some(last) if last.end == token.start => last.end = token.end,
+            _ => ranges.push(ChangedRange { start: token.start, end: token.end }),
+        }
+    }
+
+    ranges
+}
+
+/// Compare a removed line with the added line that replaced it, returning the changed ranges of
+/// each
+pub fn diff_words(old_line: &str, new_line: &str) -> (Vec<ChangedRange>, Vec<ChangedRange>) {
+    let old_tokens = tokenize(old_line);
+    let new_tokens = tokenize(new_line);
+
+    if old_tokens.len() > MAX_TOKENS || new_tokens.len() > MAX_TOKENS {
+        let whole = |tokens: &[Token]| match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => vec![ChangedRange { start: first.start, end: last.end }],
+            _ => Vec::new(),
+        };
+        return (whole(&old_tokens), whole(&new_tokens));
+    }
+
+    // Longest common subsequence of tokens, lengths[i][j] covering old[i..] and new[j..]
+    let (n, m) = (old_tokens.len(), new_tokens.len());
+    let mut lengths = vec![vec![0u16; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i][j] = if old_tokens[i].text == new_tokens[j].text {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut old_matched = vec![false; n];
+    let mut new_matched = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old_tokens[i].text == new_tokens[j].text {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    (changed_ranges(&old_tokens, &old_matched), changed_ranges(&new_tokens, &new_matched))
+}
+
+/// Annotate the lines of a hunk with their word-level changes. Each run of removed lines is
+/// paired line by line with the run of added lines directly following it; lines left without a
+/// counterpart are not annotated.
+pub fn annotate_word_changes(lines: &mut [LineDiff]) {
+    let mut index = 0;
+
+    while index < lines.len() {
+        if lines[index].origin != '-' {
+            index += 1;
+            continue;
+        }
+
+        let removed_start = index;
+        while index < lines.len() && lines[index].origin == '-' {
+            index += 1;
+        }
+        let added_start = index;
+        while index < lines.len() && lines[index].origin == '+' {
+            index += 1;
+        }
+
+        let pairs = (added_start - removed_start).min(index - added_start);
+        for pair in 0..pairs {
+            let (old_index, new_index) = (removed_start + pair, added_start + pair);
+            let (old_changes, new_changes) = diff_words(&lines[old_index].content, &lines[new_index].content);
+            lines[old_index].changes = old_changes;
+            lines[new_index].changes = new_changes;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: usize, end: usize) -> ChangedRange {
+        ChangedRange { start, end }
+    }
+
+    #[test]
+    fn marks_only_the_changed_words() {
+        let (old, new) = diff_words("let total = count + 1;", "let total = count + 2;");
+        assert_eq!(old, vec![range(20, 21)]);
+        assert_eq!(new, vec![range(20, 21)]);
+    }
+
+    #[test]
+    fn has_no_changes_for_identical_lines() {
+        assert_eq!(diff_words("let total = count;", "let total = count;"), (Vec::new(), Vec::new()));
+        assert_eq!(diff_words("", ""), (Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn counts_characters_outside_the_bmp_as_two_code_units() {
+        // Each emoji is a surrogate pair in JavaScript strings
+        let (old, new) = diff_words("say '😀' twice", "say '😃' twice");
+        assert_eq!(old, vec![range(5, 7)]);
+        assert_eq!(new, vec![range(5, 7)]);
+
+        // and shifts the ranges of the changes following it
+        let (old, new) = diff_words("😀😀 old", "😀😀 new");
+        assert_eq!(old, vec![range(5, 8)]);
+        assert_eq!(new, vec![range(5, 8)]);
+    }
+
+    #[test]
+    fn marks_lines_over_the_token_limit_as_changed_as_a_whole() {
+        // "x " is two tokens, so these lines are over the limit
+        let old = "x ".repeat(MAX_TOKENS);
+        let new = format!("{}y", old);
+
+        let (old_changes, new_changes) = diff_words(&old, &new);
+        assert_eq!(old_changes, vec![range(0, old.len())]);
+        assert_eq!(new_changes, vec![range(0, new.len())]);
+    }
+}
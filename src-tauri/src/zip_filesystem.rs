@@ -296,6 +296,56 @@ impl FileSystem {
         Ok(files)
     }
 
+    // Read every file under `prefix` in a single pass over the archive, without caching the contents
+    pub fn for_each_file_from_memory<F>(&self, prefix: &str, mut visit: F) -> Result<(), String>
+    where
+        F: FnMut(&str, &str),
+    {
+        self.ensure_archive_loaded()?;
+        let start = Instant::now();
+
+        // Get archive content from memory
+        let archive_content = {
+            let content_guard = self.archive_content.lock().unwrap();
+            match &*content_guard {
+                Some(content) => Arc::clone(content),
+                None => return Err("Archive content not loaded".to_string()),
+            }
+        };
+
+        // Create a cursor to read from memory
+        let cursor = Cursor::new(&*archive_content);
+
+        // Create ZIP archive reader
+        let mut archive = ZipArchive::new(cursor)
+            .map_err(|e| format!("Failed to open ZIP archive: {}", e))?;
+
+        let mut visited = 0;
+        let mut content = String::new();
+        for i in 0..archive.len() {
+            let mut zip_file = archive.by_index(i)
+                .map_err(|e| format!("Failed to access file in ZIP: {}", e))?;
+
+            if !zip_file.is_file() || !zip_file.name().starts_with(prefix) {
+                continue;
+            }
+
+            content.clear();
+            if let Err(e) = zip_file.read_to_string(&mut content) {
+                println!("Failed to read file '{}' from ZIP: {}", zip_file.name(), e);
+                continue;
+            }
+
+            visit(zip_file.name(), &content);
+            visited += 1;
+        }
+
+        println!("Performance: for_each_file_from_memory visited {} files under '{}' in {:?}",
+                 visited, prefix, start.elapsed());
+
+        Ok(())
+    }
+
     pub fn get_index_file(&self) -> Result<String, String> {
         let start = Instant::now();
 
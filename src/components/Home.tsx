@@ -5,6 +5,7 @@ import "../App.css";
 import "../FilesApp.css";
 import { useAppContext } from "../AppContext";
 import FileViewer from "./FileViewer";
+import MissingCommits from "./MissingCommits";
 import ThemePicker from "./ThemePicker";
 import type { PrFile } from "../types/interfaces";
 
@@ -192,6 +193,7 @@ function Home() {
                                 {repoPath ? "✅" : "🟥"}
                             </span>
                         </div>
+                        {archiveFile && repoPath && <MissingCommits />}
                     </fieldset>
                 </div>
 
@@ -0,0 +1,116 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+import { useState } from "react";
+import type {
+    FetchProgress,
+    FetchReport,
+    MissingCommitsReport,
+} from "../types/interfaces";
+
+/**
+ * Checks which commits of the archive are missing from the repository (PRs
+ * whose branches were deleted before it was cloned), and fetches them
+ */
+function MissingCommits() {
+    const [report, setReport] = useState<MissingCommitsReport | null>(null);
+    const [remote, setRemote] = useState<string>("origin");
+    const [progress, setProgress] = useState<FetchProgress | null>(null);
+    const [fetchReport, setFetchReport] = useState<FetchReport | null>(null);
+    const [busy, setBusy] = useState<boolean>(false);
+    const [error, setError] = useState<string>("");
+
+    async function scan() {
+        setBusy(true);
+        setError("");
+        setFetchReport(null);
+        try {
+            setReport(await invoke<MissingCommitsReport>("scan_missing_commits"));
+        } catch (err) {
+            setError(`Failed to check for missing commits: ${err}`);
+        } finally {
+            setBusy(false);
+        }
+    }
+
+    async function fetchMissing() {
+        if (!report) return;
+
+        setBusy(true);
+        setError("");
+        const unlisten = await listen<FetchProgress>(
+            "missing-commits-fetch-progress",
+            (event) => setProgress(event.payload),
+        );
+        try {
+            setFetchReport(
+                await invoke<FetchReport>("fetch_missing_commits", {
+                    remote,
+                    commitIds: report.missing.map((commit) => commit.commit_id),
+                }),
+            );
+            // Check again, so that the count reflects what is still missing
+            setReport(await invoke<MissingCommitsReport>("scan_missing_commits"));
+        } catch (err) {
+            setError(`Failed to fetch missing commits: ${err}`);
+        } finally {
+            unlisten();
+            setProgress(null);
+            setBusy(false);
+        }
+    }
+
+    return (
+        <div>
+            <button type="button" onClick={scan} disabled={busy}>
+                Check for missing commits
+            </button>
+            {report && (
+                <small>
+                    {report.missing.length} of {report.referenced} referenced
+                    commits missing
+                </small>
+            )}
+            {report && report.missing.length > 0 && (
+                <>
+                    <input
+                        type="text"
+                        placeholder="Remote name, URL or mirror path..."
+                        value={remote}
+                        onChange={(e) => setRemote(e.target.value)}
+                        disabled={busy}
+                    />
+                    <button
+                        type="button"
+                        onClick={fetchMissing}
+                        disabled={busy || !remote.trim()}
+                    >
+                        Fetch
+                    </button>
+                </>
+            )}
+            {progress && (
+                <small>
+                    Batch {progress.batch}/{progress.batch_count}:{" "}
+                    {progress.fetched_commits}/{progress.total_commits} commits
+                    fetched
+                    {progress.total_objects > 0 &&
+                        ` (${progress.received_objects}/${progress.total_objects} objects)`}
+                </small>
+            )}
+            {fetchReport && !progress && (
+                <small
+                    title={fetchReport.failed
+                        .map((failed) => `${failed.commit_id}: ${failed.error}`)
+                        .join("\n")}
+                >
+                    Fetched {fetchReport.fetched.length} commits
+                    {fetchReport.failed.length > 0 &&
+                        `, ${fetchReport.failed.length} failed`}
+                </small>
+            )}
+            {error && <small className="error">{error}</small>}
+        </div>
+    );
+}
+
+export default MissingCommits;
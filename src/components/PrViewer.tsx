@@ -108,12 +108,9 @@ const PrViewer: React.FC<PrViewerProps> = ({ prData, onBack }) => {
                     )}
 
                     {activeTab === "changes" &&
-                        prData.last_merge_target_commit &&
-                        prData.last_merge_commit && (
-                            <DiffViewer
-                                sourceBranch={prData.last_merge_commit}
-                                targetBranch={prData.last_merge_target_commit}
-                            />
+                        prData.last_merge_source_commit &&
+                        prData.last_merge_target_commit && (
+                            <DiffViewer prNumber={prData.id} />
                         )}
 
                     {activeTab === "changes" &&
@@ -1,9 +1,18 @@
 import React, { useState, useEffect, useMemo, memo } from "react";
 import { invoke } from "@tauri-apps/api/core";
-import type { TreeDiff, FileDiff, DiffLine } from "../../types/interfaces";
+import type {
+    DiffSettings,
+    PrDiff,
+    FileDiff,
+    FileDiffSummary,
+    DiffHunk,
+    DiffLine,
+    ChangedRange,
+    HighlightSpan,
+} from "../../types/interfaces";
 import diffstyle from "./styles/diff.module.css";
 import style from "../PrViewer.module.css" with { type: "css" };
-import { Highlight, themes } from "prism-react-renderer";
+import { Highlight, themes, type Token } from "prism-react-renderer";
 import "./styles/diff.overrides.css";
 import { useTheme } from "../../hooks/useTheme";
 
@@ -123,12 +132,112 @@ function findCodeHunkByLineNumber(
     return undefined;
 }
 
+/**
+ * Build the tokens of a line from the highlighting computed by the backend over
+ * the whole file, in the shape of Prism's tokens so the theme applies to them
+ */
+function tokensFromHighlights(
+    content: string,
+    highlights: HighlightSpan[],
+): Token[] {
+    const tokens: Token[] = [];
+    let offset = 0;
+
+    for (const span of highlights) {
+        // Lines are trimmed by the backend, which can cut off the end of a span
+        const start = Math.min(span.start, content.length);
+        const end = Math.min(span.end, content.length);
+        if (start > offset) {
+            tokens.push({
+                types: ["plain"],
+                content: content.slice(offset, start),
+            });
+        }
+        if (end > start) {
+            tokens.push({ types: [span.kind], content: content.slice(start, end) });
+        }
+        offset = Math.max(offset, end);
+    }
+
+    if (offset < content.length) {
+        tokens.push({ types: ["plain"], content: content.slice(offset) });
+    }
+    if (tokens.length === 0) {
+        tokens.push({ types: ["plain"], content: "\n", empty: true });
+    }
+
+    return tokens;
+}
+
+/**
+ * Split the highlighted tokens of a line at the boundaries of its word-level
+ * changes, so the changed parts can be marked without losing highlighting
+ */
+function splitTokensByChanges<T extends { content: string }>(
+    tokens: T[],
+    changes: ChangedRange[],
+): { token: T; changed: boolean }[] {
+    if (changes.length === 0) {
+        return tokens.map((token) => ({ token, changed: false }));
+    }
+
+    const isChanged = (offset: number) =>
+        changes.some((range) => offset >= range.start && offset < range.end);
+    const boundaries = changes.flatMap((range) => [range.start, range.end]);
+
+    const parts: { token: T; changed: boolean }[] = [];
+    let offset = 0;
+    for (const token of tokens) {
+        const end = offset + token.content.length;
+        const cuts = [
+            offset,
+            ...boundaries.filter((b) => b > offset && b < end),
+            end,
+        ].sort((a, b) => a - b);
+
+        for (let i = 0; i < cuts.length - 1; i++) {
+            parts.push({
+                token: {
+                    ...token,
+                    content: token.content.slice(
+                        cuts[i] - offset,
+                        cuts[i + 1] - offset,
+                    ),
+                },
+                changed: isChanged(cuts[i]),
+            });
+        }
+        offset = end;
+    }
+
+    return parts;
+}
+
 interface DiffViewerProps {
-    sourceBranch: string;
-    targetBranch: string;
-    filePattern?: string;
+    prNumber: number;
+}
+
+// The revisions a PR's diff is between, as resolved by `git_get_pr_diff`
+interface PrDiffRevisions {
+    base: string;
+    source: string;
+    baseFromArchive: boolean;
 }
 
+// The list entry of a file whose lines are already known
+const summarizeFileDiff = (file: FileDiff): FileDiffSummary => {
+    const lines = file.hunks.flatMap((hunk) => hunk.lines);
+    return {
+        old_file: file.old_file,
+        new_file: file.new_file,
+        status: file.status,
+        binary: file.binary,
+        similarity: file.similarity,
+        additions: lines.filter((line) => line.origin === "+").length,
+        deletions: lines.filter((line) => line.origin === "-").length,
+    };
+};
+
 // Renders a file's diff content as a grid for better text selection
 const FileContentGrid = memo(
     ({
@@ -172,6 +281,11 @@ const FileContentGrid = memo(
             Array(1 + (idxUnexpectedLineCount.get(i) || 0)).fill(line),
         );
 
+        // Lines highlighted by the backend don't need to be tokenized again
+        const highlightedByBackend = lines.every(
+            (line) => line.highlights !== null,
+        );
+
         // Function to determine if a line should be highlighted
         const shouldHighlightLine = (lineNumber: number | null) => {
             if (!highlightLineRange || lineNumber === null) return false;
@@ -250,7 +364,7 @@ const FileContentGrid = memo(
                             : themes.nightOwlLight
                     }
                     code={codeText}
-                    language="tsx"
+                    language={highlightedByBackend ? "plain" : "tsx"}
                 >
                     {({
                         className,
@@ -296,12 +410,31 @@ const FileContentGrid = memo(
                                             )?.type
                                         }
                                     >
-                                        {line.map((token, key) => (
-                                            <span
-                                                key={key}
-                                                {...getTokenProps({ token })}
-                                            />
-                                        ))}
+                                        {splitTokensByChanges(
+                                            filledOutLines[i]?.highlights
+                                                ? tokensFromHighlights(
+                                                      filledOutLines[i].content,
+                                                      filledOutLines[i]
+                                                          .highlights,
+                                                  )
+                                                : line,
+                                            filledOutLines[i]?.changes ?? [],
+                                        ).map(({ token, changed }, key) => {
+                                            const props = getTokenProps({
+                                                token,
+                                            });
+                                            return (
+                                                <span
+                                                    key={key}
+                                                    {...props}
+                                                    className={
+                                                        changed
+                                                            ? `${props.className} ${diffstyle.wordChange}`
+                                                            : props.className
+                                                    }
+                                                />
+                                            );
+                                        })}
                                     </div>
                                 );
                             })}
@@ -313,33 +446,201 @@ const FileContentGrid = memo(
     },
 );
 
+// How many lines each click on "expand" loads
+const CONTEXT_EXPAND_STEP = 20;
+
+// A hunk without new lines starts after its start line, as in its header
+const firstNewLine = (hunk: DiffHunk) =>
+    hunk.new_lines === 0 ? hunk.new_start + 1 : hunk.new_start;
+
+// A single hunk, which can be collapsed to its header and have its context expanded
+const HunkView = memo(
+    ({
+        hunk,
+        previousHunk,
+        isLastHunk,
+        filePath,
+        fromRevision,
+        toRevision,
+        highlightLineRange,
+        isTargetFile,
+    }: {
+        hunk: DiffHunk;
+        previousHunk: DiffHunk | undefined;
+        isLastHunk: boolean;
+        filePath: string;
+        fromRevision: string;
+        toRevision: string;
+        highlightLineRange?: { start: number; end: number } | null;
+        isTargetFile: boolean;
+    }) => {
+        const [isCollapsed, setIsCollapsed] = useState(false);
+        const [linesAbove, setLinesAbove] = useState<DiffLine[]>([]);
+        const [linesBelow, setLinesBelow] = useState<DiffLine[]>([]);
+        const [reachedEnd, setReachedEnd] = useState(false);
+
+        // Lines between this hunk and the previous one (or the start of the file)
+        // that aren't shown yet
+        const hiddenAbove =
+            firstNewLine(hunk) -
+            (previousHunk
+                ? firstNewLine(previousHunk) + previousHunk.new_lines
+                : 1) -
+            linesAbove.length;
+
+        const expand = async (direction: "above" | "below") => {
+            const count =
+                direction === "above"
+                    ? Math.min(CONTEXT_EXPAND_STEP, hiddenAbove)
+                    : CONTEXT_EXPAND_STEP;
+            // Expand from the lines already added on that side, which extend the
+            // hunk. A side without lines starts after its start line, as in the header
+            const shown = direction === "above" ? linesAbove : linesBelow;
+            const extendedStart = (start: number, count: number) => {
+                if (shown.length === 0) return start;
+                const first = count === 0 ? start + 1 : start;
+                return direction === "above" ? first - shown.length : first;
+            };
+            try {
+                const context = await invoke<DiffLine[]>(
+                    "git_get_context_lines",
+                    {
+                        filePath,
+                        fromRevision,
+                        toRevision,
+                        hunk: {
+                            old_start: extendedStart(
+                                hunk.old_start,
+                                hunk.old_lines,
+                            ),
+                            old_lines: hunk.old_lines + shown.length,
+                            new_start: extendedStart(
+                                hunk.new_start,
+                                hunk.new_lines,
+                            ),
+                            new_lines: hunk.new_lines + shown.length,
+                        },
+                        direction,
+                        count,
+                        highlight: true,
+                    },
+                );
+                if (direction === "above") {
+                    setLinesAbove([...context, ...linesAbove]);
+                } else {
+                    setLinesBelow([...linesBelow, ...context]);
+                    setReachedEnd(context.length < count);
+                }
+            } catch (err) {
+                console.error("Context error:", err);
+            }
+        };
+
+        const lines = useMemo(
+            () => [...linesAbove, ...hunk.lines, ...linesBelow],
+            [linesAbove, hunk.lines, linesBelow],
+        );
+
+        return (
+            <div>
+                {/* biome-ignore lint/a11y/useKeyWithClickEvents: same as the file header */}
+                <div
+                    className={diffstyle.hunkHeader}
+                    onClick={() => setIsCollapsed((collapsed) => !collapsed)}
+                >
+                    <span className={diffstyle.hunkToggle}>
+                        {isCollapsed ? "►" : "▼"}
+                    </span>
+                    {hunk.header}
+                    {!isCollapsed && hiddenAbove > 0 && (
+                        <button
+                            type="button"
+                            className={diffstyle.expandContext}
+                            onClick={(e) => {
+                                e.stopPropagation();
+                                expand("above");
+                            }}
+                        >
+                            ↑ Expand
+                        </button>
+                    )}
+                </div>
+                {!isCollapsed && (
+                    <FileContentGrid
+                        lines={lines}
+                        highlightLineRange={highlightLineRange}
+                        isTargetFile={isTargetFile}
+                    />
+                )}
+                {!isCollapsed && isLastHunk && !reachedEnd && (
+                    <button
+                        type="button"
+                        className={diffstyle.expandContext}
+                        onClick={() => expand("below")}
+                    >
+                        ↓ Expand
+                    </button>
+                )}
+            </div>
+        );
+    },
+);
+
 // Memoized file content component
 const FileContent = memo(
     ({
+        summary,
         file,
+        error,
+        fromRevision,
+        toRevision,
         isExpanded,
         highlightLineRange,
         isTargetFile,
     }: {
-        file: FileDiff;
+        summary: FileDiffSummary;
+        fromRevision: string;
+        toRevision: string;
+        file: FileDiff | null;
+        error: string | null;
         isExpanded: boolean;
         highlightLineRange?: { start: number; end: number } | null;
         isTargetFile: boolean;
     }) => {
         if (!isExpanded) return null;
 
+        if (error) {
+            return <div className={style.error}>{error}</div>;
+        }
+
+        if (!file && !summary.binary) {
+            return <div className={style.loading}>Loading file diff...</div>;
+        }
+
         return (
             <div className={style["diff-file-content"]}>
-                {file.binary ? (
+                {summary.binary || !file ? (
                     <div className={style["diff-binary"]}>
                         Binary file not shown
                     </div>
                 ) : (
-                    <FileContentGrid
-                        lines={file.lines}
-                        highlightLineRange={highlightLineRange}
-                        isTargetFile={isTargetFile}
-                    />
+                    file.hunks.map((hunk, hunkIndex) => (
+                        <HunkView
+                            key={`${hunk.old_start}-${hunk.new_start}`}
+                            hunk={hunk}
+                            previousHunk={file.hunks[hunkIndex - 1]}
+                            isLastHunk={hunkIndex === file.hunks.length - 1}
+                            filePath={
+                                file.status === "D"
+                                    ? file.old_file
+                                    : file.new_file
+                            }
+                            fromRevision={fromRevision}
+                            toRevision={toRevision}
+                            highlightLineRange={highlightLineRange}
+                            isTargetFile={isTargetFile}
+                        />
+                    ))
                 )}
             </div>
         );
@@ -353,7 +654,7 @@ const FileHeader = memo(
         isExpanded,
         onToggle,
     }: {
-        file: FileDiff;
+        file: FileDiffSummary;
         isExpanded: boolean;
         onToggle: () => void;
     }) => {
@@ -386,6 +687,11 @@ const FileHeader = memo(
                 <span className={style["diff-file-name"]}>
                     {file.status === "D" ? file.old_file : file.new_file}
                 </span>
+                {!file.binary && (
+                    <span className={style["diff-file-stats"]}>
+                        +{file.additions} -{file.deletions}
+                    </span>
+                )}
                 <span className={style["diff-file-toggle"]}>
                     {isExpanded ? "▼" : "►"}
                 </span>
@@ -399,13 +705,17 @@ const FileView = memo(
     ({
         file,
         fileIndex,
+        fromRevision,
+        toRevision,
         isExpanded,
         onToggle,
         isTargetFile,
         highlightLineRange,
     }: {
-        file: FileDiff;
+        file: FileDiffSummary;
         fileIndex: number;
+        fromRevision: string;
+        toRevision: string;
         isExpanded: boolean;
         onToggle: () => void;
         isTargetFile: boolean;
@@ -413,6 +723,25 @@ const FileView = memo(
     }) => {
         const fileRef = React.useRef<HTMLDivElement>(null);
         const headerRef = React.useRef<HTMLDivElement>(null);
+        const [fileDiff, setFileDiff] = useState<FileDiff | null>(null);
+        const [fileError, setFileError] = useState<string | null>(null);
+
+        // Only load the lines of a file once it is expanded
+        useEffect(() => {
+            if (!isExpanded || fileDiff || file.binary) return;
+
+            invoke<FileDiff>("git_get_file_diff", {
+                fromRevision,
+                toRevision,
+                filePath: file.status === "D" ? file.old_file : file.new_file,
+                highlight: true,
+            })
+                .then(setFileDiff)
+                .catch((err) => {
+                    setFileError(`Failed to get file diff: ${err}`);
+                    console.error("File diff error:", err);
+                });
+        }, [isExpanded, fileDiff, file, fromRevision, toRevision]);
 
         // Scroll to this file if it's the target file
         // The specific line scrolling is handled by the FileContentGrid
@@ -474,7 +803,11 @@ const FileView = memo(
                     />
                 </div>
                 <FileContent
-                    file={file}
+                    summary={file}
+                    file={fileDiff}
+                    error={fileError}
+                    fromRevision={fromRevision}
+                    toRevision={toRevision}
                     isExpanded={isExpanded}
                     highlightLineRange={highlightLineRange}
                     isTargetFile={isTargetFile}
@@ -484,12 +817,33 @@ const FileView = memo(
     },
 );
 
-export const DiffViewer: React.FC<DiffViewerProps> = ({
-    sourceBranch,
-    targetBranch,
-    filePattern,
-}) => {
-    const [treeDiff, setTreeDiff] = useState<TreeDiff | null>(null);
+// Number of unchanged lines shown around each change, for all the diffs
+const ContextLinesInput = ({
+    settings,
+    onChange,
+}: {
+    settings: DiffSettings;
+    onChange: (settings: DiffSettings) => void;
+}) => (
+    <label className={diffstyle.contextLinesSetting}>
+        Context lines
+        <input
+            type="number"
+            min={0}
+            max={100}
+            value={settings.context_lines}
+            onChange={(e) => {
+                const contextLines = Number.parseInt(e.target.value, 10);
+                if (Number.isNaN(contextLines) || contextLines < 0) return;
+                onChange({ ...settings, context_lines: contextLines });
+            }}
+        />
+    </label>
+);
+
+export const DiffViewer: React.FC<DiffViewerProps> = ({ prNumber }) => {
+    const [files, setFiles] = useState<FileDiffSummary[] | null>(null);
+    const [revisions, setRevisions] = useState<PrDiffRevisions | null>(null);
     const [loading, setLoading] = useState(true);
     const [error, setError] = useState<string | null>(null);
     const [expandedFiles, setExpandedFiles] = useState<Record<string, boolean>>(
@@ -502,6 +856,21 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
         start: number;
         end: number;
     } | null>(null);
+    const [diffSettings, setDiffSettings] = useState<DiffSettings | null>(
+        null,
+    );
+
+    useEffect(() => {
+        invoke<DiffSettings>("get_diff_settings")
+            .then(setDiffSettings)
+            .catch((err) => console.error("Diff settings error:", err));
+    }, []);
+
+    const updateDiffSettings = (settings: DiffSettings) => {
+        invoke("set_diff_settings", { settings })
+            .then(() => setDiffSettings(settings))
+            .catch((err) => console.error("Diff settings error:", err));
+    };
 
     // Check for file to scroll to from sessionStorage
     useEffect(() => {
@@ -542,21 +911,23 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
             setLoading(true);
             setError(null);
             try {
-                const diff = await (filePattern
-                    ? invoke<TreeDiff>("git_get_file_diff_between_revisions", {
-                          fromRevision: targetBranch,
-                          toRevision: sourceBranch,
-                          filePattern: filePattern || null,
-                      })
-                    : invoke<TreeDiff>("git_get_tree_diff_between_revisions", {
-                          fromRevision: targetBranch,
-                          toRevision: sourceBranch,
-                      }));
-                setTreeDiff(diff);
+                // The PR's changes against the merge base of its branches, as in
+                // Azure DevOps. Expanded files are reloaded between the same commits
+                // with highlighting.
+                const diff = await invoke<PrDiff>("git_get_pr_diff", {
+                    prNumber,
+                });
+                const summaries = diff.files.map(summarizeFileDiff);
+                setFiles(summaries);
+                setRevisions({
+                    base: diff.base_commit,
+                    source: diff.source_commit,
+                    baseFromArchive: diff.base_from_archive,
+                });
 
                 // Initialize expanded state for each file
                 const initialExpandedState: Record<string, boolean> = {};
-                diff.files.forEach((file) => {
+                summaries.forEach((file) => {
                     // Auto-expand the file to scroll to, or all files if none specified
                     const isTargetFile =
                         fileToScrollTo &&
@@ -575,7 +946,7 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
         };
 
         fetchDiff();
-    }, [sourceBranch, targetBranch, filePattern, fileToScrollTo]);
+    }, [prNumber, fileToScrollTo]);
 
     const toggleFileExpansion = (fileName: string) => {
         setExpandedFiles((prev) => ({
@@ -585,13 +956,21 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
     };
 
     const metadata = useMemo(
-        () => (
-            <span className={diffstyle.filesChangedRefMeta}>
-                <code>{targetBranch}</code> <code>--&gt;</code>{" "}
-                <code>{sourceBranch}</code>
-            </span>
-        ),
-        [sourceBranch, targetBranch],
+        () =>
+            revisions && (
+                <span className={diffstyle.filesChangedRefMeta}>
+                    <code title={revisions.base}>
+                        {revisions.base.slice(0, 8)}
+                    </code>{" "}
+                    <code>--&gt;</code>{" "}
+                    <code title={revisions.source}>
+                        {revisions.source.slice(0, 8)}
+                    </code>
+                    {!revisions.baseFromArchive &&
+                        " (merge base computed locally)"}
+                </span>
+            ),
+        [revisions],
     );
 
     if (loading) {
@@ -606,7 +985,7 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
         );
     }
 
-    if (!treeDiff || treeDiff.files.length === 0) {
+    if (!files || !revisions || files.length === 0) {
         return <div>No differences found between branches. {metadata}</div>;
     }
 
@@ -614,10 +993,16 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
         <div className={style["pr-section"]}>
             <div className={style["diff-header"]}>
                 <h3>Files Changed</h3>
+                {diffSettings && (
+                    <ContextLinesInput
+                        settings={diffSettings}
+                        onChange={updateDiffSettings}
+                    />
+                )}
                 {metadata}
             </div>
             <div className={style["diff-container"]}>
-                {treeDiff.files.map((file, fileIndex) => {
+                {files.map((file, fileIndex) => {
                     const isTargetFile =
                         fileToScrollTo &&
                         (file.new_file === fileToScrollTo ||
@@ -625,9 +1010,13 @@ export const DiffViewer: React.FC<DiffViewerProps> = ({
 
                     return (
                         <FileView
-                            key={file.new_file || file.old_file}
+                            // Remounting reloads the lines of expanded files
+                            // with the new context
+                            key={`${file.new_file || file.old_file}-${diffSettings?.context_lines}`}
                             file={file}
                             fileIndex={fileIndex}
+                            fromRevision={revisions.base}
+                            toRevision={revisions.source}
                             isExpanded={expandedFiles[file.new_file]}
                             onToggle={() => toggleFileExpansion(file.new_file)}
                             isTargetFile={!!isTargetFile}
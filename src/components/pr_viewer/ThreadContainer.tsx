@@ -1,27 +1,24 @@
-import { invoke } from "@tauri-apps/api/core";
-import React, { type DependencyList, type ReactNode, useEffect, useState } from "react";
+import React from "react";
 import { useNavigate, useParams } from "react-router-dom";
-import type { Thread } from "../../types/interfaces";
+import type {
+    DiffLineInfo,
+    Thread,
+    ThreadSnippet,
+} from "../../types/interfaces";
 import style from "../PrViewer.module.css" with { type: "css" };
 import { ThreadComment } from "./ThreadComment";
 import diffstyle from "./styles/diff.module.css";
 
-function useAsyncMemo<T>(
-    fn: () => Promise<T>,
-    deps: DependencyList,
-): T | undefined {
-    const [value, setValue] = useState<T>();
-    useEffect(() => {
-        fn().then(setValue);
-    }, [fn, ...deps]);
-    return value;
-}
-
 interface ThreadContainerProps {
     thread: Thread;
+    // The code the thread comments on, loaded for all threads at once
+    snippet?: ThreadSnippet;
 }
 
-export const ThreadContainer: React.FC<ThreadContainerProps> = ({ thread }) => {
+export const ThreadContainer: React.FC<ThreadContainerProps> = ({
+    thread,
+    snippet,
+}) => {
     const navigate = useNavigate();
     const { prNumber } = useParams<{ prNumber: string }>();
     let filePath = "";
@@ -59,35 +56,16 @@ export const ThreadContainer: React.FC<ThreadContainerProps> = ({ thread }) => {
         }
     };
 
-    const diffFileContent = useAsyncMemo(async () => {
-        if (
-            thread.threadContext?.filePath &&
-            thread.threadContext.rightFileStart &&
-            thread.threadContext.rightFileEnd &&
-            thread.pullRequestThreadContext?.firstIterationDetails
-        ) {
-            return (await invoke("git_get_file_diff_between_revisions", {
-                // git2 wants relative paths, and all these state with `/` -- it
-                // also doesn't want the value to start with '.'
-                filePath: thread.threadContext.filePath.slice(1),
-                fromRevision:
-                    thread.pullRequestThreadContext?.firstIterationDetails
-                        .targetCommit,
-                toRevision:
-                    thread.pullRequestThreadContext?.firstIterationDetails
-                        .sourceCommit,
-                startLine: thread.threadContext.rightFileStart.line,
-                endLine: thread.threadContext.rightFileEnd.line,
-            })) as string;
-        }
-        return undefined;
-    }, [
-        thread.threadContext,
-        thread.threadContext?.filePath,
-        thread.threadContext?.rightFileStart,
-        thread.threadContext?.rightFileStart,
-        thread.pullRequestThreadContext?.firstIterationDetails,
-    ]);
+    // Threads on deleted lines only have the lines themselves
+    const snippetLines: DiffLineInfo[] | undefined =
+        snippet?.diff ??
+        snippet?.lines?.map((content, index) => ({
+            origin: "-",
+            old_lineno: snippet.start_line + index,
+            new_lineno: null,
+            content,
+            in_range: true,
+        }));
 
     return (
         <div className={style.thread}>
@@ -116,25 +94,15 @@ export const ThreadContainer: React.FC<ThreadContainerProps> = ({ thread }) => {
                 </div>
             </div>
 
-            {diffFileContent && (
-                //  style={{ backgroundColor: "lightblue" }}
+            {snippetLines && snippetLines.length > 0 && (
                 <pre>
-                    {diffFileContent
-                        .split("\n")
-                        .reduce((accum, line, idx, lines) => {
-                            if (line.trim() !== "") {
-                                // biome-ignore lint/suspicious/noArrayIndexKey: no better key available, really
-                                accum.push(<DiffLine key={idx} line={line} />);
-                            }
-                            if (idx !== lines.length - 1) {
-                                accum.push(
-                                    <React.Fragment key={`${idx}c`}>
-                                        {"\n"}
-                                    </React.Fragment>,
-                                );
-                            }
-                            return accum;
-                        }, [] as ReactNode[])}
+                    {snippetLines.map((line, idx) => (
+                        // biome-ignore lint/suspicious/noArrayIndexKey: no better key available, really
+                        <React.Fragment key={idx}>
+                            {idx > 0 && "\n"}
+                            <DiffLine line={line} />
+                        </React.Fragment>
+                    ))}
                 </pre>
             )}
 
@@ -156,13 +124,32 @@ export const ThreadContainer: React.FC<ThreadContainerProps> = ({ thread }) => {
     );
 };
 
-function DiffLine({ line }: { line: string }) {
-    switch (line[0]) {
+function originClass(origin: string) {
+    switch (origin) {
         case "+":
-            return <span className={diffstyle.diffAdd}>{line}</span>;
+            return diffstyle.diffAdd;
         case "-":
-            return <span className={diffstyle.diffRemove}>{line}</span>;
+            return diffstyle.diffRemove;
         default:
-            return <span className={diffstyle.diffUnchanged}>{line}</span>;
+            return diffstyle.diffUnchanged;
     }
 }
+
+function DiffLine({ line }: { line: DiffLineInfo }) {
+    const lineNumber = line.new_lineno ?? line.old_lineno ?? "?";
+    const content = line.content.replace(/\r?\n$/, "");
+    const className = [
+        originClass(line.origin),
+        // Context around the thread's lines is dimmed
+        !line.in_range && diffstyle.diffContext,
+    ]
+        .filter(Boolean)
+        .join(" ");
+
+    return (
+        <span className={className}>
+            {line.origin}
+            {lineNumber} {content}
+        </span>
+    );
+}
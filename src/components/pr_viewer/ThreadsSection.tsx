@@ -1,6 +1,8 @@
+import { invoke } from "@tauri-apps/api/core";
 import type React from "react";
-import { useState } from "react";
-import type { Thread } from "../../types/interfaces";
+import { useEffect, useState } from "react";
+import { useParams } from "react-router-dom";
+import type { Thread, ThreadSnippet } from "../../types/interfaces";
 import { SystemNotification } from "./SystemNotification";
 import { ThreadContainer } from "./ThreadContainer";
 import { ThreadFilter } from "./ThreadFilter";
@@ -12,8 +14,36 @@ interface ThreadsSectionProps {
 
 export const ThreadsSection: React.FC<ThreadsSectionProps> = ({ threads }) => {
     const [filterType, setFilterType] = useState<"all" | "comments">("all");
+    const [snippets, setSnippets] = useState<Map<number, ThreadSnippet>>(
+        new Map(),
+    );
+    const { prNumber } = useParams<{ prNumber: string }>();
     const activeThreads = threads.filter((thread) => !thread.isDeleted);
 
+    // Read the code of all the threads in one request, so that threads on the
+    // same file share a single diff
+    useEffect(() => {
+        if (!prNumber) return;
+
+        let cancelled = false;
+        invoke<ThreadSnippet[]>("get_thread_snippets", {
+            prNumber: Number(prNumber),
+        })
+            .then((results) => {
+                if (cancelled) return;
+                setSnippets(
+                    new Map(
+                        results.map((snippet) => [snippet.thread_id, snippet]),
+                    ),
+                );
+            })
+            .catch((err) => console.error("Failed to read thread code:", err));
+
+        return () => {
+            cancelled = true;
+        };
+    }, [prNumber]);
+
     if (activeThreads.length === 0) {
         return (
             <div className={style["threads-section"]}>
@@ -86,6 +116,7 @@ export const ThreadsSection: React.FC<ThreadsSectionProps> = ({ threads }) => {
                     <ThreadContainer
                         key={`thread-${threadIndex}`}
                         thread={thread}
+                        snippet={snippets.get(thread.id)}
                     />
                 );
             })}
@@ -1,7 +1,11 @@
 // components/pr/helpers/systemMessageParser.ts
 import type React from "react";
 import { useState } from "react";
-import type { Comment, Thread } from "../../../types/interfaces";
+import type {
+    Comment,
+    CommitMetadata,
+    Thread,
+} from "../../../types/interfaces";
 import { invoke } from "@tauri-apps/api/core";
 import style from "../../PrViewer.module.css" with { type: "css" };
 
@@ -72,27 +76,6 @@ function getVoteText(vote: number): string {
     }
 }
 
-type CommitMetadata = {
-    /// The full commit hash
-    commit_id: string;
-    /// The commit message
-    message: string;
-    /// The commit message summary (first line)
-    summary: string;
-    /// The author's name
-    author_name: string;
-    /// The author's email
-    author_email: string;
-    /// The author time (when the commit was originally created)
-    author_time: Date;
-    /// The committer's name
-    committer_name: string;
-    /// The committer's email
-    committer_email: string;
-    /// The commit time (when the commit was added to the repository)
-    commit_time: Date;
-};
-
 export function RenderCommits({
     commentContent,
     thread,
@@ -6,8 +6,25 @@ export interface PrFile {
     author: string;
     status: string;
     creation_date: string;
+    completion_date: string | null;
     source_branch: string;
     target_branch: string;
+    reviewers: PrReviewer[];
+    // Optional, as older archives were exported without them
+    description: string | null;
+    is_draft: boolean | null;
+    repository: string | null;
+    reviewer_count: number | null;
+    has_conflicts: boolean | null;
+    work_item_count: number | null;
+    thread_count: number | null;
+}
+
+export interface PrReviewer {
+    id: string;
+    displayName: string;
+    vote: number; // 10=approved, 5=approved with suggestions, 0=no vote, -5=waiting for author, -10=rejected
+    isRequired: boolean;
 }
 
 export interface PrIndexEntry {
@@ -48,20 +65,222 @@ export interface DiffLine {
     new_lineno: number | null;
     content: string;
     origin: string; // '+' for addition, '-' for deletion, ' ' for context
+    changes: ChangedRange[]; // Changed ranges within the line, with word diff enabled
+    highlights: HighlightSpan[] | null; // Syntax highlighting, when enabled and the language is known
+}
+
+// A range of a line, in string indices
+export interface ChangedRange {
+    start: number;
+    end: number;
+}
+
+// A highlighted token of a line, in string indices. The kinds are Prism token types
+export interface HighlightSpan {
+    start: number;
+    end: number;
+    kind:
+        | 'comment'
+        | 'string'
+        | 'keyword'
+        | 'operator'
+        | 'number'
+        | 'boolean'
+        | 'constant'
+        | 'function'
+        | 'class-name'
+        | 'tag'
+        | 'attr-name'
+        | 'punctuation';
+}
+
+// Lines of a file at a revision, from `get_git_file_lines_at_revision`
+export interface FileLines {
+    lines: string[];
+    highlights: HighlightSpan[][] | null;
+}
+
+export interface DiffHunk {
+    old_start: number;
+    old_lines: number;
+    new_start: number;
+    new_lines: number;
+    header: string; // e.g. "@@ -12,7 +12,8 @@ fn main() {"
+    lines: DiffLine[];
 }
 
 export interface FileDiff {
     old_file: string;
     new_file: string;
     status: string; // 'A' for added, 'M' for modified, 'D' for deleted
-    lines: DiffLine[];
+    hunks: DiffHunk[];
     binary: boolean;
+    similarity: number | null; // Percentage, for renamed ('R') and copied ('C') files
+}
+
+export interface DiffSettings {
+    detect_renames: boolean;
+    detect_copies: boolean;
+    similarity_threshold: number; // Minimum percentage for a rename or copy
+    context_lines: number; // Unchanged lines shown around each change
+    ignore_whitespace: boolean;
+    ignore_whitespace_change: boolean;
+    ignore_whitespace_eol: boolean;
+    word_diff: boolean; // Annotate changed ranges within lines
+    syntax_highlight: boolean; // Highlight lines over their whole file, off by default
 }
 
 export interface TreeDiff {
     files: FileDiff[];
 }
 
+// A changed file without its lines, which are loaded separately
+export interface FileDiffSummary {
+    old_file: string;
+    new_file: string;
+    status: string;
+    binary: boolean;
+    similarity: number | null;
+    additions: number;
+    deletions: number;
+}
+
+export interface TreeDiffSummary {
+    files: FileDiffSummary[];
+}
+
+// A PR's changes against its merge base, as in Azure DevOps' "Files" tab
+export interface PrDiff extends TreeDiff {
+    base_commit: string;
+    source_commit: string;
+    base_from_archive: boolean; // false when the merge base was computed locally
+}
+
+// A range of lines of a file to diff, for `git_get_file_diffs_between_revisions`
+export interface FileDiffRequest {
+    file_path: string;
+    from_revision: string;
+    to_revision: string;
+    start_line: number;
+    end_line: number;
+}
+
+// A line of the diff of a range of a file, with the context around the range
+export interface DiffLineInfo {
+    old_lineno: number | null;
+    new_lineno: number | null;
+    content: string; // With its line ending
+    origin: string; // '+' for addition, '-' for deletion, ' ' for context
+    in_range: boolean; // false for the context around the range
+}
+
+export interface FileDiffResult {
+    diff: DiffLineInfo[] | null;
+    error: string | null;
+}
+
+// The code a thread comments on, from `get_thread_snippets`
+export interface ThreadSnippet {
+    thread_id: number;
+    file_path: string;
+    from_commit: string | null; // Set for diffs
+    to_commit: string | null; // The commit the lines are numbered in
+    start_line: number;
+    end_line: number;
+    diff: DiffLineInfo[] | null; // For threads on the changed side of the file
+    lines: string[] | null; // For threads on deleted lines
+    error: string | null;
+}
+
+export interface BlameLine {
+    line_number: number;
+    content: string;
+    commit_id: string;
+    author_name: string;
+    author_email: string;
+    author_time: string; // ISO 8601, with the author's UTC offset
+    original_line_number: number;
+    original_path: string | null;
+    pr_number: number | null; // PR that introduced the line, if known
+}
+
+export interface CommitMetadata {
+    /// The full commit hash
+    commit_id: string;
+    /// The commit message
+    message: string;
+    /// The commit message summary (first line)
+    summary: string;
+    /// The author's name
+    author_name: string;
+    /// The author's email
+    author_email: string;
+    /// The author time (when the commit was originally created), as an ISO 8601
+    /// string with the author's UTC offset
+    author_time: string;
+    /// The committer's name
+    committer_name: string;
+    /// The committer's email
+    committer_email: string;
+    /// The commit time (when the commit was added to the repository), as an ISO
+    /// 8601 string with the committer's UTC offset
+    commit_time: string;
+    /// The full hashes of the parent commits, the first parent first
+    parent_ids: string[];
+    /// Changes against the first parent
+    files_changed: number;
+    /// Lines added and removed, null when not counted (get_pr_commits without
+    /// line_stats)
+    insertions: number | null;
+    deletions: number | null;
+    /// The kind of signature the commit carries, if signed (not verified)
+    signature: 'gpg' | 'ssh' | 'x509' | 'unknown' | null;
+    /// Trailers at the end of the message, e.g. `Co-authored-by`
+    trailers: CommitTrailer[];
+    /// Whether invalid UTF-8 in the names, emails or message was replaced
+    lossy_utf8: boolean;
+}
+
+export interface CommitTrailer {
+    key: string;
+    value: string;
+}
+
+export interface PrCommitMatch {
+    pr_number: number;
+    role: 'merge' | 'source' | 'iteration';
+    matched_commit: string; // The commit itself, or the mainline commit that merged it
+    via_ancestry: boolean;
+}
+
+// A commit referenced by the archive that the repository doesn't have
+export interface MissingCommit {
+    commit_id: string;
+    pr_numbers: number[]; // PRs referencing the commit
+}
+
+export interface MissingCommitsReport {
+    referenced: number; // Distinct commits referenced by the archive
+    missing: MissingCommit[];
+    fetch_script: string; // Shell script fetching the missing commits in batches
+}
+
+// Emitted as `missing-commits-fetch-progress` while fetching missing commits
+export interface FetchProgress {
+    batch: number; // 1-based
+    batch_count: number;
+    fetched_commits: number;
+    total_commits: number;
+    received_objects: number; // For the current batch
+    total_objects: number;
+    received_bytes: number;
+}
+
+export interface FetchReport {
+    fetched: string[];
+    failed: { commit_id: string; error: string }[];
+}
+
 export interface PrData {
     id: number;
     title: string;
@@ -93,12 +312,7 @@ export interface PrData {
         bypassReason?: string;
     };
     completion_queue_time?: string;
-    reviewers?: {
-        id: string;
-        displayName: string;
-        vote: number;
-        isRequired: boolean;
-    }[];
+    reviewers?: PrReviewer[];
     threads?: Thread[];
 }
 